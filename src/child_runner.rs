@@ -0,0 +1,283 @@
+/*
+ * Runs a command as our child and watches it and its descendants until it
+ * exits, then publishes a summary of the whole run.
+ */
+use std::cmp;
+use std::io;
+use std::mem;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, Child, ExitStatus};
+use std::sync::atomic::{Ordering, AtomicBool};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use libc;
+use time::precise_time_ns;
+
+use proc_fs::{ProcFs, Pid};
+use proc_fs::tree::SubtreeTotals;
+use metrics_sender::{MetricSender, Metric, Target, RunSummary};
+use metrics_sender::target::Selector;
+
+use marid::{MaridError, Runner, Signal, Receiver};
+
+// How often we check whether the command has exited.
+const POLL_MS: u32 = 100;
+
+pub struct ChildRunner {
+    argv: Vec<String>,
+    child: Option<Child>,
+    started: u64, // precise_time_ns
+    proc_fs: ProcFs,
+    metrics: MetricSender,
+    interval_ms: u32,
+    exit_code: Arc<Mutex<Option<i32>>>,
+}
+
+impl ChildRunner {
+    /// `interval` is how often, in seconds, the command is sampled and its
+    /// metrics are published through `metrics`.
+    pub fn new(argv: Vec<String>, proc_fs: ProcFs, metrics: MetricSender, interval: u32) -> ChildRunner {
+        ChildRunner {
+            argv: argv,
+            child: None,
+            started: 0,
+            proc_fs: proc_fs,
+            metrics: metrics,
+            interval_ms: cmp::max(interval * 1000, POLL_MS),
+            exit_code: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Where the exit code for translucence itself is stored once the
+    /// command has exited: the command's own, or 128 + signal.
+    pub fn exit_code(&self) -> Arc<Mutex<Option<i32>>> {
+        self.exit_code.clone()
+    }
+
+    // Publishes totals for the tree and returns them, so the run summary can
+    // keep the peaks.
+    fn sample(&mut self, pid: u32) -> Option<SubtreeTotals> {
+        self.metrics.send_metrics();
+        match self.proc_fs.process_subtree_totals(Pid::Id(pid)) {
+            Ok(totals) => {
+                match self.metrics.publish(Metric::Subtree(totals.clone())) {
+                    Ok(_) => {},
+                    Err(ref e) => warn!("Error sending subtree metrics: {}", e),
+                }
+                Some(totals)
+            },
+            Err(ref e) => {
+                debug!("Could not read process tree of {}: {}", pid, e);
+                None
+            },
+        }
+    }
+}
+
+fn signal_number(signal: Signal) -> Option<libc::c_int> {
+    match signal {
+        Signal::HUP => Some(libc::SIGHUP),
+        Signal::INT => Some(libc::SIGINT),
+        Signal::QUIT => Some(libc::SIGQUIT),
+        Signal::TERM => Some(libc::SIGTERM),
+        _ => None,
+    }
+}
+
+// Passes every signal we receive on to the command until it has exited.
+fn forward_signals(signals: Receiver<Signal>, pid: u32, exited: Arc<AtomicBool>) {
+    let _thread = thread::spawn(move || {
+        for signal in signals.iter() {
+            if exited.load(Ordering::SeqCst) {
+                return
+            }
+            match signal_number(signal) {
+                Some(signum) => {
+                    debug!("Forwarding {:?} to {}", signal, pid);
+                    unsafe { libc::kill(pid as libc::pid_t, signum) };
+                },
+                None => debug!("Not forwarding {:?}", signal),
+            }
+        }
+    });
+}
+
+fn timeval_ms(tv: libc::timeval) -> u64 {
+    tv.tv_sec as u64 * 1000 + tv.tv_usec as u64 / 1000
+}
+
+// Resource usage of all children we have waited for, which includes the
+// descendants they waited for in turn.
+fn children_usage() -> libc::rusage {
+    unsafe {
+        let mut usage: libc::rusage = mem::zeroed();
+        libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage);
+        usage
+    }
+}
+
+impl Runner for ChildRunner {
+    fn setup(&mut self) -> Result<(), MaridError> {
+        if self.argv.is_empty() {
+            return Err(Box::new(io::Error::new(io::ErrorKind::InvalidInput, "no command to run")))
+        }
+
+        let child = try!(Command::new(&self.argv[0]).args(&self.argv[1..]).spawn()
+                         .map_err(|e| Box::new(e) as MaridError));
+        info!("Started {} as {}", self.argv[0], child.id());
+
+        let name = Path::new(&self.argv[0]).file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or(self.argv[0].clone());
+        self.metrics.add_target(Target {
+            name: name,
+            selector: Selector::Tree(Pid::Id(child.id())),
+        });
+        self.started = precise_time_ns();
+        self.child = Some(child);
+        Ok(())
+    }
+
+    fn run(mut self: Box<Self>, signals: Receiver<Signal>) -> Result<(), MaridError> {
+        debug!("Running ChildRunner");
+        let mut child = self.child.take().expect("ChildRunner was not set up");
+        let pid = child.id();
+
+        let exited = Arc::new(AtomicBool::new(false));
+        forward_signals(signals, pid, exited.clone());
+
+        let (exit_sn, exit_rc) = mpsc::channel();
+        let waiter_exited = exited.clone();
+        let _waiter = thread::spawn(move || {
+            let status = child.wait();
+            waiter_exited.store(true, Ordering::SeqCst);
+            let _ = exit_sn.send(status);
+        });
+
+        let mut peak = SubtreeTotals::default();
+        let mut status: Option<ExitStatus> = None;
+        let mut since_sample = self.interval_ms;
+        while status.is_none() {
+            if since_sample >= self.interval_ms {
+                if let Some(totals) = self.sample(pid) {
+                    peak.rss = cmp::max(peak.rss, totals.rss);
+                    peak.read_bytes = cmp::max(peak.read_bytes, totals.read_bytes);
+                    peak.write_bytes = cmp::max(peak.write_bytes, totals.write_bytes);
+                }
+                since_sample = 0;
+            }
+
+            thread::sleep_ms(POLL_MS);
+            since_sample += POLL_MS;
+            match exit_rc.try_recv() {
+                Ok(Ok(exit_status)) => status = Some(exit_status),
+                Ok(Err(e)) => return Err(Box::new(e)),
+                Err(TryRecvError::Empty) => {},
+                Err(TryRecvError::Disconnected) =>
+                    return Err(Box::new(io::Error::new(io::ErrorKind::Other,
+                                                       "lost track of the command"))),
+            }
+        }
+
+        let status = status.unwrap();
+        let wall_ms = (precise_time_ns() - self.started) / 1000000;
+        let usage = children_usage();
+        let summary = RunSummary {
+            argv: self.argv.clone(),
+            pid: pid,
+            exit_code: status.code(),
+            signal: status.signal(),
+            wall_ms: wall_ms,
+            user_cpu_ms: timeval_ms(usage.ru_utime),
+            system_cpu_ms: timeval_ms(usage.ru_stime),
+            // ru_maxrss is in kB and only covers the largest single process,
+            // the samples cover the whole tree but may miss short peaks.
+            peak_rss: cmp::max(peak.rss, usage.ru_maxrss as usize * 1024),
+            // ru_inblock and ru_oublock count 512 byte blocks.
+            read_bytes: cmp::max(peak.read_bytes, usage.ru_inblock as usize * 512),
+            write_bytes: cmp::max(peak.write_bytes, usage.ru_oublock as usize * 512),
+        };
+        info!("{} exited with {} after {} ms", self.argv[0], status, wall_ms);
+
+        *self.exit_code.lock().expect("Lock was poisoned") =
+            Some(status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)));
+
+        match self.metrics.publish(Metric::RunSummary(summary)) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use transit::udp::*;
+    use super::*;
+    use proc_fs::ProcFs;
+    use metrics_sender::{MetricSender, MetricPacket, Metric};
+    use chan;
+    use marid::{Signal, Runner};
+
+    fn runner(argv: &[&str], metric_addr: &str, listen_addr: &str) -> ChildRunner {
+        let metrics = MetricSender::new(metric_addr,
+                                        String::from(listen_addr),
+                                        String::from("test-runner"),
+                                        1,
+                                        ProcFs::default()).unwrap();
+        ChildRunner::new(argv.iter().map(|a| String::from(*a)).collect(),
+                         ProcFs::default(), metrics, 1)
+    }
+
+    // Receives packets until the run summary arrives.
+    fn summary(listener: &mut Transit) -> RunSummary {
+        loop {
+            let (packet, _addr): (MetricPacket, _) = listener.recv_from().unwrap();
+            if let Metric::RunSummary(summary) = packet.data {
+                return summary
+            }
+        }
+    }
+
+    #[test]
+    fn test_child_runner() {
+        let mut runner = Box::new(runner(&["sh", "-c", "exit 3"], "127.0.0.1:60010", "127.0.0.1:60011"));
+        let mut listener = Transit::new("127.0.0.1:60011").unwrap();
+        let exit_code = runner.exit_code();
+
+        let (_sn, rc) = chan::sync(1);
+        assert!(runner.setup().is_ok());
+        assert!(runner.run(rc).is_ok());
+
+        let summary = summary(&mut listener);
+        assert_eq!(summary.argv, vec!["sh", "-c", "exit 3"]);
+        assert_eq!(summary.exit_code, Some(3));
+        assert_eq!(summary.signal, None);
+        assert!(summary.peak_rss > 0);
+        assert_eq!(*exit_code.lock().unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_child_runner_forwards_signals() {
+        let mut runner = Box::new(runner(&["sleep", "10"], "127.0.0.1:60012", "127.0.0.1:60013"));
+        let mut listener = Transit::new("127.0.0.1:60013").unwrap();
+
+        let (sn, rc) = chan::sync(1);
+        assert!(runner.setup().is_ok());
+        sn.send(Signal::TERM);
+        assert!(runner.run(rc).is_ok());
+
+        let summary = summary(&mut listener);
+        assert_eq!(summary.exit_code, None);
+        assert_eq!(summary.signal, Some(15));
+        assert!(summary.wall_ms < 10000);
+    }
+
+    #[test]
+    fn test_child_runner_no_command() {
+        let mut runner = runner(&["/nonexistent/command"], "127.0.0.1:60014", "127.0.0.1:60015");
+        assert!(runner.setup().is_err());
+    }
+}
@@ -13,6 +13,8 @@ extern crate serde;
 extern crate serde_json;
 
 extern crate time;
+extern crate libc;
+extern crate regex;
 
 extern crate transit;
 extern crate marid;
@@ -24,11 +26,32 @@ extern crate docopt;
 
 // Write the Docopt usage string with the `docopt!` macro.
 docopt!(Config, "
-Usage: translucence [options] <metrics-address>
+Usage: translucence [options] [--target SPEC]... <metrics-address>
+       translucence run [options] [--target SPEC]... <metrics-address> -- <command>...
 
-    Run the translucence process with specified parameters.
+    Run the translucence process with specified parameters. With run, start
+    <command>, publish metrics for it and its descendants until it exits, then
+    publish a summary of the run and exit with its exit code.
 Options:
-    -r SECONDS  The registration interval [default: 10]
+    -r SECONDS         The registration interval [default: 10]
+    --proc-root PATH   Root of the proc filesystem to read [default: /proc]
+    --target SPEC      Publish metrics for the processes matching SPEC, one of
+                       NAME=pid:PID, NAME=pidfile:PATH, NAME=comm:COMM,
+                       NAME=cmdline:REGEX or NAME=tree:PID. May be repeated.
+    --subtree PID      Also publish totals for the process tree rooted at PID
+    --iface-include RE  Only publish counters for the network interfaces
+                        whose name matches RE
+    --iface-exclude RE  Do not publish counters for the network interfaces
+                        whose name matches RE, e.g. '^(lo|veth)'
+    --whole-disks      Only publish block device stats for whole disks,
+                       leaving out partitions and loop devices
+    --fs-types LIST    Comma separated filesystem types to publish capacity
+                       for, e.g. ext4,xfs, instead of every local one. NFS,
+                       CIFS and FUSE mounts are only published when listed
+                       here, and a hung server stalls every send.
+    --redact-env LIST  Comma separated environment variable name fragments
+                       whose values /proc/:pid/info hides, instead of the
+                       built in list of PASSWORD, SECRET, TOKEN, KEY etc.
 ");
 
 
@@ -37,29 +60,110 @@ mod proc_fs;
 mod metrics_sender;
 mod util;
 mod router_runner;
+mod child_runner;
 
 use marid::{launch, Composer, Runner, Signal, Process};
+use proc_fs::{ProcFs, Pid};
+use proc_fs::identity::DEFAULT_REDACT;
+use proc_fs::netdev::InterfaceFilter;
+use proc_fs::diskstats::DiskFilter;
+use proc_fs::filesystems::FilesystemFilter;
+use metrics_sender::Target;
 use std::error::Error;
+use std::io::{self, Write};
 use std::str::FromStr;
 
+use regex::Regex;
+
 fn main() {
     env_logger::init().unwrap();
     let config: Config = Config::docopt().decode().unwrap_or_else(|e| e.exit());
 
-    let router = Box::new(router_runner::RouterRunner::new()) as Box<Runner + Send>;
-
+    let proc_fs = ProcFs::new(config.flag_proc_root.as_str());
     let metric_addr = String::from(config.arg_metrics_address.as_str());
     let publish_interval = match u32::from_str(config.flag_r.as_str()) {
         Ok(n) => n,
         Err(_) => 10, // Default to 10 seconds
     };
     let origin = String::from("translucence");
-    let metrics = Box::new(metrics_sender::MetricSender::new("0.0.0.0:0",
-                                                             metric_addr,
-                                                             origin,
-                                                             publish_interval).unwrap())
+    let mut metrics = metrics_sender::MetricSender::new("0.0.0.0:0",
+                                                        metric_addr,
+                                                        origin,
+                                                        publish_interval,
+                                                        proc_fs.clone()).unwrap();
+    if !config.flag_subtree.is_empty() {
+        match Pid::from_str(config.flag_subtree.as_str()) {
+            Ok(pid) => metrics.monitor_subtree(pid),
+            Err(e) => {
+                writeln!(io::stderr(), "--subtree: {}", e).unwrap();
+                std::process::exit(1);
+            },
+        }
+    }
+    if !config.flag_iface_include.is_empty() || !config.flag_iface_exclude.is_empty() {
+        let regex = |flag: &str, re: &str| if re.is_empty() {
+            None
+        } else {
+            match Regex::new(re) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    writeln!(io::stderr(), "{}: {}", flag, e).unwrap();
+                    std::process::exit(1);
+                },
+            }
+        };
+        metrics.filter_interfaces(InterfaceFilter {
+            include: regex("--iface-include", config.flag_iface_include.as_str()),
+            exclude: regex("--iface-exclude", config.flag_iface_exclude.as_str()),
+        });
+    }
+    if config.flag_whole_disks {
+        metrics.filter_disks(DiskFilter { skip_partitions: true, skip_loop: true });
+    }
+    if !config.flag_fs_types.is_empty() {
+        metrics.filter_filesystems(FilesystemFilter {
+            fs_types: config.flag_fs_types.split(',')
+                .map(|t| String::from(t.trim()))
+                .filter(|t| !t.is_empty())
+                .collect(),
+            mount_point: None,
+        });
+    }
+    for spec in config.flag_target.iter() {
+        match Target::from_str(spec.as_str()) {
+            Ok(target) => metrics.add_target(target),
+            Err(e) => {
+                writeln!(io::stderr(), "--target: {}", e).unwrap();
+                std::process::exit(1);
+            },
+        }
+    }
+
+    if config.cmd_run {
+        let runner = child_runner::ChildRunner::new(config.arg_command.clone(),
+                                                    proc_fs,
+                                                    metrics,
+                                                    publish_interval);
+        let exit_code = runner.exit_code();
+        let process = launch(runner, vec!(Signal::INT, Signal::TERM, Signal::HUP, Signal::QUIT));
+        process.wait().expect("Error while running");
+        drop(process);
+
+        let code = *exit_code.lock().expect("Lock was poisoned");
+        std::process::exit(code.unwrap_or(1));
+    }
+
+    let redact = if config.flag_redact_env.is_empty() {
+        DEFAULT_REDACT.iter().map(|r| String::from(*r)).collect()
+    } else {
+        config.flag_redact_env.split(',')
+            .map(|r| String::from(r.trim()))
+            .filter(|r| !r.is_empty())
+            .collect()
+    };
+    let router = Box::new(router_runner::RouterRunner::new(proc_fs.clone(), redact))
         as Box<Runner + Send>;
-
+    let metrics = Box::new(metrics) as Box<Runner + Send>;
     let composer = Composer::new(vec!(router, metrics));
     let process = launch(composer, vec!(Signal::INT, Signal::TERM));
     process.wait().expect("Error while running");
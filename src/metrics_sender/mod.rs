@@ -1,4 +1,5 @@
 use transit::udp::*;
+use std::collections::{BTreeMap, BTreeSet};
 use std::net::{ToSocketAddrs};
 use std::error::Error;
 use std::fmt;
@@ -6,30 +7,88 @@ use std::sync::atomic::{Ordering, AtomicBool};
 use std::sync::{Arc};
 
 use time::{get_time};
-use proc_fs::stats::{ProcStatm};
-use proc_fs::net::{process_tcp, TcpStat};
+use proc_fs::stats::{ProcStat, ProcStatm, ProcIo};
+use proc_fs::status::ProcStatus;
+use proc_fs::memory::MemoryUsage;
+use proc_fs::fds::FdCounts;
+use proc_fs::limits::Headroom;
+use proc_fs::tree::SubtreeTotals;
+use proc_fs::net::{NetworkStat, UdpStat, TcpExtStat, udp_stat, tcp_ext_stat};
+use proc_fs::sockstat::SocketSummary;
+use proc_fs::diskstats::{DiskSampler, DiskFilter, Disk};
+use proc_fs::filesystems::{FilesystemFilter, Filesystem};
+use proc_fs::cpu::{CpuSampler, ProcessCpu};
+use proc_fs::system::{SystemCpuSampler, SystemCpu};
+use proc_fs::meminfo::{SystemMemorySampler, SystemMemoryRates};
+use proc_fs::load::{LoadAvg, SystemPressure};
+use proc_fs::netdev::{InterfaceSampler, InterfaceFilter, Interface};
+use proc_fs::threads::ThreadStat;
+use proc_fs::{ProcFs, Pid, ProcError};
 use util::handle_signals_atomic;
 
 use marid::{MaridError, Runner, Receiver, Signal};
 
+pub mod target;
+
+pub use self::target::Target;
+
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MetricPacket {
-    origin: String,
-    timestamp: i64,
+    pub origin: String,
+    pub timestamp: i64,
+    pub target: Option<String>, // name of the monitored target, None for ourselves
+    pub command: String, // comm of the measured process
+    pub argv_hash: u64, // with the timestamp, tells restarts apart
     // ip: IpAddr,
-    data: Metric,
+    pub data: Metric,
+}
+
+// What a command run by child_runner did over its whole life.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub argv: Vec<String>,
+    pub pid: u32,
+    pub exit_code: Option<i32>, // None if the command was killed by a signal
+    pub signal: Option<i32>, // signal that killed the command
+    pub wall_ms: u64,
+    pub user_cpu_ms: u64, // of the command and all descendants it waited for
+    pub system_cpu_ms: u64,
+    pub peak_rss: usize, // bytes, the largest RSS of the whole tree we saw
+    pub read_bytes: usize, // from storage, by the whole tree
+    pub write_bytes: usize,
 }
 
-#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Metric {
-    Network(TcpStat),
+    Network(NetworkStat),
+    Udp(UdpStat),
+    TcpExt(TcpExtStat),
+    Sockets(SocketSummary),
     Process(ProcStatm),
+    Stat(ProcStat),
+    Io(ProcIo),
+    Status(ProcStatus),
+    Memory(MemoryUsage),
+    Fds(FdCounts),
+    Headroom(Vec<Headroom>),
+    Subtree(SubtreeTotals),
+    ProcessCpu(ProcessCpu),
+    RunSummary(RunSummary),
+    SystemCpu(SystemCpu),
+    SystemMemory(SystemMemoryRates),
+    Load(LoadAvg),
+    Pressure(SystemPressure),
+    Interfaces(Vec<Interface>),
+    Disk(Vec<Disk>),
+    Filesystems(Vec<Filesystem>),
+    Threads(Vec<ThreadStat>),
 }
 
 #[derive(Debug)]
 pub enum MetricError {
     UDPError(Box<Error + Sync + Send>),
+    ProcError(ProcError),
 }
 
 impl From<TransitError> for MetricError {
@@ -38,16 +97,24 @@ impl From<TransitError> for MetricError {
     }
 }
 
+impl From<ProcError> for MetricError {
+    fn from(err: ProcError) -> MetricError {
+        MetricError::ProcError(err)
+    }
+}
+
 impl Error for MetricError {
     fn description(&self) -> &str {
         match *self {
             MetricError::UDPError(ref err) => err.description(),
+            MetricError::ProcError(ref err) => err.description(),
         }
     }
 
     fn cause(&self) -> Option<&Error> {
         match *self {
             MetricError::UDPError(ref err) => err.cause(),
+            MetricError::ProcError(ref err) => err.cause(),
         }
     }
 }
@@ -57,6 +124,8 @@ impl fmt::Display for MetricError {
         match *self {
             MetricError::UDPError(ref err) =>
                 write!(fmt, "UDPError: {}", err),
+            MetricError::ProcError(ref err) =>
+                write!(fmt, "ProcError: {}", err),
         }
     }
 }
@@ -66,41 +135,383 @@ pub struct MetricSender {
     transit: Transit,
     metric_addr: String,
     publish_interval: u32,
+    proc_fs: ProcFs,
+    command: String,
+    argv_hash: u64,
+    cpu_sampler: CpuSampler,
+    system_cpu_sampler: SystemCpuSampler,
+    system_memory_sampler: SystemMemorySampler,
+    interface_sampler: InterfaceSampler,
+    interface_filter: InterfaceFilter,
+    disk_sampler: DiskSampler,
+    filesystem_filter: FilesystemFilter,
+    subtree: Option<Pid>,
+    targets: Vec<Target>,
+    target_samplers: BTreeMap<(String, u32), CpuSampler>,
     shutdown: Arc<AtomicBool>,
 }
 
 impl MetricSender {
-    pub fn new<A>(addr: A, metric_addr: String, origin: String, interval: u32)
+    pub fn new<A>(addr: A, metric_addr: String, origin: String, interval: u32, proc_fs: ProcFs)
         -> Result<MetricSender, MetricError> where A: ToSocketAddrs {
             let transit = try!(Transit::new(addr));
+            let identity = try!(proc_fs.process_identity(Pid::Current, None));
             Ok(MetricSender {
                 origin: origin,
                 transit: transit,
                 metric_addr: metric_addr,
                 publish_interval: interval,
+                command: identity.command,
+                argv_hash: identity.argv_hash,
+                cpu_sampler: CpuSampler::new(proc_fs.clone(), Pid::Current),
+                system_cpu_sampler: SystemCpuSampler::new(proc_fs.clone()),
+                system_memory_sampler: SystemMemorySampler::new(proc_fs.clone()),
+                interface_sampler: InterfaceSampler::new(proc_fs.clone(), InterfaceFilter::default()),
+                interface_filter: InterfaceFilter::default(),
+                disk_sampler: DiskSampler::new(proc_fs.clone(), DiskFilter::default()),
+                filesystem_filter: FilesystemFilter::default(),
+                subtree: None,
+                targets: Vec::new(),
+                target_samplers: BTreeMap::new(),
+                proc_fs: proc_fs,
                 shutdown: Arc::new(AtomicBool::new(false)),
             })
         }
 
+    /// Also publish totals for the process tree rooted at `pid`, e.g. a
+    /// supervisor and its workers.
+    pub fn monitor_subtree(&mut self, pid: Pid) {
+        self.subtree = Some(pid);
+    }
+
+    /// Only publish counters for the interfaces matching `filter`.
+    pub fn filter_interfaces(&mut self, filter: InterfaceFilter) {
+        self.interface_sampler = InterfaceSampler::new(self.proc_fs.clone(), filter.clone());
+        self.interface_filter = filter;
+    }
+
+    /// Only publish stats for the block devices matching `filter`.
+    pub fn filter_disks(&mut self, filter: DiskFilter) {
+        self.disk_sampler = DiskSampler::new(self.proc_fs.clone(), filter);
+    }
+
+    /// Only publish capacity for the filesystems matching `filter`.
+    pub fn filter_filesystems(&mut self, filter: FilesystemFilter) {
+        self.filesystem_filter = filter;
+    }
+
+    /// Publish statm, stat, status, io, memory, fd, headroom, thread and cpu
+    /// metrics for every process matching `target`, tagged with its name.
+    pub fn add_target(&mut self, target: Target) {
+        self.targets.push(target);
+    }
+
     pub fn send_to<A>(&mut self, data: Metric, addr: A) -> Result<(), MetricError> where A: ToSocketAddrs {
+        let command = self.command.clone();
+        let argv_hash = self.argv_hash;
+        self.send_packet(None, command, argv_hash, data, addr)
+    }
+
+    fn send_packet<A>(&mut self, target: Option<&str>, command: String, argv_hash: u64,
+                      data: Metric, addr: A) -> Result<(), MetricError> where A: ToSocketAddrs {
         // let address = try!(self.transit.local_addr());
         let time = get_time().sec;
         let pkt = MetricPacket {
             origin: self.origin.clone(),
             timestamp: time,
+            target: target.map(String::from),
+            command: command,
+            argv_hash: argv_hash,
             // ip: address.ip(),
             data: data,
         };
         try!(self.transit.send_to(&pkt, addr));
         Ok(())
     }
+
+    /// Sends one round of every configured metric to the metric address.
+    pub fn send_metrics(&mut self) {
+        let metric_addr = self.metric_addr.clone();
+
+        match self.send_tcp(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending tcp metrics: {}", e),
+        }
+
+        match self.send_snmp(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending snmp metrics: {}", e),
+        }
+
+        match self.send_sockets(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending socket metrics: {}", e),
+        }
+
+        match self.send_cpu(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending cpu metrics: {}", e),
+        }
+
+        match self.send_system_cpu(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending system cpu metrics: {}", e),
+        }
+
+        match self.send_system_memory(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending system memory metrics: {}", e),
+        }
+
+        match self.send_load(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending load metrics: {}", e),
+        }
+
+        match self.send_pressure(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending pressure metrics: {}", e),
+        }
+
+        match self.send_interfaces(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending interface metrics: {}", e),
+        }
+
+        match self.send_disks(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending disk metrics: {}", e),
+        }
+
+        match self.send_filesystems(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending filesystem metrics: {}", e),
+        }
+
+        match self.send_fds(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending fd metrics: {}", e),
+        }
+
+        match self.send_headroom(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending headroom metrics: {}", e),
+        }
+
+        match self.send_subtree(metric_addr.as_str()) {
+            Ok(_) => {},
+            Err(ref e) => warn!("Error sending subtree metrics: {}", e),
+        }
+
+        self.send_targets(metric_addr.as_str());
+    }
+
+    /// Sends a single metric to the metric address.
+    pub fn publish(&mut self, data: Metric) -> Result<(), MetricError> {
+        let metric_addr = self.metric_addr.clone();
+        self.send_to(data, metric_addr.as_str())
+    }
+
+    fn send_tcp(&mut self, addr: &str) -> Result<(), MetricError> {
+        let mut network = try!(self.proc_fs.process_network(Pid::Current));
+        // Metric::Interfaces already carries our interfaces, with rates.
+        network.interfaces.clear();
+        self.send_to(Metric::Network(network), addr)
+    }
+
+    fn send_snmp(&mut self, addr: &str) -> Result<(), MetricError> {
+        let snmp = try!(self.proc_fs.net_snmp());
+        let udp = try!(udp_stat(&self.proc_fs.path("net/snmp"), &snmp));
+        try!(self.send_to(Metric::Udp(udp), addr));
+        let tcp_ext = try!(tcp_ext_stat(&self.proc_fs.path("net/netstat"), &snmp));
+        self.send_to(Metric::TcpExt(tcp_ext), addr)
+    }
+
+    fn send_sockets(&mut self, addr: &str) -> Result<(), MetricError> {
+        let summary = try!(self.proc_fs.socket_summary());
+        self.send_to(Metric::Sockets(summary), addr)
+    }
+
+    fn send_cpu(&mut self, addr: &str) -> Result<(), MetricError> {
+        match try!(self.cpu_sampler.sample()) {
+            Some(usage) => self.send_to(Metric::ProcessCpu(usage), addr),
+            None => Ok(()),
+        }
+    }
+
+    fn send_system_cpu(&mut self, addr: &str) -> Result<(), MetricError> {
+        match try!(self.system_cpu_sampler.sample()) {
+            Some(usage) => self.send_to(Metric::SystemCpu(usage), addr),
+            None => Ok(()),
+        }
+    }
+
+    fn send_system_memory(&mut self, addr: &str) -> Result<(), MetricError> {
+        let memory = try!(self.system_memory_sampler.sample());
+        self.send_to(Metric::SystemMemory(memory), addr)
+    }
+
+    fn send_load(&mut self, addr: &str) -> Result<(), MetricError> {
+        let loadavg = try!(self.proc_fs.loadavg());
+        self.send_to(Metric::Load(loadavg), addr)
+    }
+
+    // Nothing is sent on kernels without PSI.
+    fn send_pressure(&mut self, addr: &str) -> Result<(), MetricError> {
+        let pressure = try!(self.proc_fs.system_pressure());
+        if !pressure.is_available() {
+            return Ok(())
+        }
+        self.send_to(Metric::Pressure(pressure), addr)
+    }
+
+    fn send_interfaces(&mut self, addr: &str) -> Result<(), MetricError> {
+        let interfaces = try!(self.interface_sampler.sample());
+        self.send_to(Metric::Interfaces(interfaces), addr)
+    }
+
+    fn send_disks(&mut self, addr: &str) -> Result<(), MetricError> {
+        let disks = try!(self.disk_sampler.sample());
+        self.send_to(Metric::Disk(disks), addr)
+    }
+
+    fn send_filesystems(&mut self, addr: &str) -> Result<(), MetricError> {
+        let filesystems = try!(self.proc_fs.filesystems(Pid::Current, &self.filesystem_filter));
+        self.send_to(Metric::Filesystems(filesystems), addr)
+    }
+
+    fn send_fds(&mut self, addr: &str) -> Result<(), MetricError> {
+        let counts = try!(self.proc_fs.process_fd_counts(Pid::Current));
+        self.send_to(Metric::Fds(counts), addr)
+    }
+
+    fn send_headroom(&mut self, addr: &str) -> Result<(), MetricError> {
+        let headroom = try!(self.proc_fs.process_headroom(Pid::Current));
+        self.send_to(Metric::Headroom(headroom), addr)
+    }
+
+    fn send_subtree(&mut self, addr: &str) -> Result<(), MetricError> {
+        let pid = match self.subtree {
+            Some(pid) => pid,
+            None => return Ok(()),
+        };
+        let totals = try!(self.proc_fs.process_subtree_totals(pid));
+        self.send_to(Metric::Subtree(totals), addr)
+    }
+
+    fn send_targets(&mut self, addr: &str) {
+        let mut resolved = Vec::new();
+        for target in self.targets.iter() {
+            match target.resolve(&self.proc_fs) {
+                Ok(pids) => resolved.extend(pids.into_iter().map(|pid| (target.name.clone(), pid))),
+                Err(ref e) => warn!("Error resolving target {}: {}", target.name, e),
+            }
+        }
+
+        // Keep the cpu samplers of processes that are still running, so the
+        // samplers of exited or restarted targets start over.
+        let proc_fs = self.proc_fs.clone();
+        let mut samplers = BTreeMap::new();
+        for key in resolved.iter() {
+            let sampler = self.target_samplers.remove(key)
+                .unwrap_or_else(|| CpuSampler::new(proc_fs.clone(), Pid::Id(key.1)));
+            samplers.insert(key.clone(), sampler);
+        }
+        self.target_samplers = samplers;
+
+        for &(ref name, pid) in resolved.iter() {
+            match self.send_target(name, pid, addr) {
+                Ok(_) => {},
+                // The process exited since we resolved the target.
+                Err(MetricError::ProcError(ProcError::NotFound(_))) => {},
+                Err(ref e) => warn!("Error sending metrics for target {} ({}): {}", name, pid, e),
+            }
+        }
+
+        // Network counters belong to a namespace rather than a process, so
+        // they are sent once per namespace, and not at all for targets in
+        // ours.
+        let mut namespaces = BTreeSet::new();
+        namespaces.extend(self.proc_fs.process_net_namespace(Pid::Current).ok());
+        for &(ref name, pid) in resolved.iter() {
+            match self.send_target_network(name, pid, &mut namespaces, addr) {
+                Ok(_) => {},
+                Err(MetricError::ProcError(ProcError::NotFound(_))) => {},
+                Err(ref e) => warn!("Error sending network metrics for target {} ({}): {}", name, pid, e),
+            }
+        }
+    }
+
+    fn send_target_network(&mut self, name: &str, pid: u32, namespaces: &mut BTreeSet<u64>,
+                           addr: &str) -> Result<(), MetricError> {
+        let net_ns = match self.proc_fs.process_net_namespace(Pid::Id(pid)) {
+            Ok(net_ns) => Some(net_ns),
+            // We cannot tell which namespace it is in, but its counters are
+            // still readable, so send them for every such target.
+            Err(ProcError::PermissionDenied(_)) => None,
+            Err(e) => return Err(MetricError::from(e)),
+        };
+        if let Some(net_ns) = net_ns {
+            if !namespaces.insert(net_ns) {
+                return Ok(())
+            }
+        }
+
+        let identity = try!(self.proc_fs.process_identity(Pid::Id(pid), None));
+        let interfaces = try!(self.proc_fs.process_net_dev(Pid::Id(pid)));
+        let network = NetworkStat {
+            net_ns: net_ns,
+            tcp: try!(self.proc_fs.process_net_tcp(Pid::Id(pid))),
+            interfaces: self.interface_filter.apply(interfaces),
+        };
+        self.send_packet(Some(name), identity.command, identity.argv_hash,
+                         Metric::Network(network), addr)
+    }
+
+    fn send_target(&mut self, name: &str, pid: u32, addr: &str) -> Result<(), MetricError> {
+        let identity = try!(self.proc_fs.process_identity(Pid::Id(pid), None));
+        let statm = try!(self.proc_fs.process_statm(Pid::Id(pid)));
+        let stat = try!(self.proc_fs.process_stat(Pid::Id(pid)));
+        let status = try!(self.proc_fs.process_status(Pid::Id(pid)));
+        let threads = try!(self.proc_fs.process_threads(Pid::Id(pid)));
+        // These need ptrace access, which we lack for other users' processes.
+        let io = try!(unless_denied(self.proc_fs.process_io(Pid::Id(pid))));
+        let memory = try!(unless_denied(self.proc_fs.process_smaps_rollup(Pid::Id(pid))));
+        let fds = try!(unless_denied(self.proc_fs.process_fd_counts(Pid::Id(pid))));
+        let headroom = try!(unless_denied(self.proc_fs.process_headroom(Pid::Id(pid))));
+        let cpu = match self.target_samplers.get_mut(&(String::from(name), pid)) {
+            Some(sampler) => try!(sampler.sample()),
+            None => None,
+        };
+
+        let mut metrics = vec![Metric::Process(statm), Metric::Stat(stat), Metric::Status(status),
+                               Metric::Threads(threads)];
+        metrics.extend(io.map(Metric::Io));
+        metrics.extend(memory.map(Metric::Memory));
+        metrics.extend(fds.map(Metric::Fds));
+        metrics.extend(headroom.map(Metric::Headroom));
+        metrics.extend(cpu.map(Metric::ProcessCpu));
+        for metric in metrics.into_iter() {
+            try!(self.send_packet(Some(name), identity.command.clone(), identity.argv_hash,
+                                  metric, addr));
+        }
+        Ok(())
+    }
+}
+
+// A collector we may not be allowed to run for the process reads as None.
+fn unless_denied<T>(result: Result<T, ProcError>) -> Result<Option<T>, MetricError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ProcError::PermissionDenied(_)) => Ok(None),
+        Err(e) => Err(MetricError::from(e)),
+    }
 }
 
 impl Runner for MetricSender {
     fn run(mut self: Box<Self>, signals: Receiver<Signal>) -> Result<(), MaridError> {
         debug!("Running MetricSender");
         handle_signals_atomic(signals, self.shutdown.clone());
-        let metric_addr = self.metric_addr.clone();
 
         loop {
             debug!("Checking for signals");
@@ -110,19 +521,7 @@ impl Runner for MetricSender {
             }
 
             debug!("Attempting to send metrics...");
-            let tcp = match process_tcp() {
-                Ok(t) => t,
-                Err(ref e) => {
-                    warn!("Error getting tcp info: {}", e);
-                    continue
-                },
-            };
-
-            let data = Metric::Network(tcp);
-            match self.send_to(data, metric_addr.as_str()) {
-                Ok(_) => {},
-                Err(ref e) => warn!("Error sending metrics: {}", e),
-            }
+            self.send_metrics();
 
             debug!("metrics sent");
             ::std::thread::sleep_ms(self.publish_interval * 1000);
@@ -141,7 +540,8 @@ impl Runner for MetricSender {
 mod test {
     use transit::udp::*;
     use super::*;
-    use proc_fs::stats::*;
+    use std::str::FromStr;
+    use proc_fs::ProcFs;
     use chan;
     use std::thread;
     use marid::{Signal, Runner};
@@ -153,10 +553,36 @@ mod test {
         let mut metric_sender = MetricSender::new(metric_addr,
                                                   String::from(listen_addr),
                                                   String::from("test-sender"),
-                                                  10).unwrap();
+                                                  10,
+                                                  ProcFs::default()).unwrap();
+        let mut listener = Transit::new(listen_addr).unwrap();
+
+        let data = Metric::Process(ProcFs::default().process_statm(Pid::Current).unwrap());
+
+        let res = metric_sender.send_to(data.clone(), listen_addr);
+        assert!(res.is_ok());
+        let res = listener.recv_from();
+        assert!(res.is_ok());
+        let (net_data, _addr): (MetricPacket, _) = res.unwrap();
+        assert_eq!(data, net_data.data);
+
+        let identity = ProcFs::default().process_identity(Pid::Current, None).unwrap();
+        assert_eq!(net_data.command, identity.command);
+        assert_eq!(net_data.argv_hash, identity.argv_hash);
+    }
+
+    #[test]
+    fn test_send_stat() {
+        let metric_addr = "127.0.0.1:60004";
+        let listen_addr = "127.0.0.1:60005";
+        let mut metric_sender = MetricSender::new(metric_addr,
+                                                  String::from(listen_addr),
+                                                  String::from("test-sender"),
+                                                  10,
+                                                  ProcFs::default()).unwrap();
         let mut listener = Transit::new(listen_addr).unwrap();
 
-        let data = Metric::Process(process_statm(String::from("self")).unwrap());
+        let data = Metric::Stat(ProcFs::default().process_stat(Pid::Current).unwrap());
 
         let res = metric_sender.send_to(data.clone(), listen_addr);
         assert!(res.is_ok());
@@ -166,6 +592,33 @@ mod test {
         assert_eq!(data, net_data.data);
     }
 
+    #[test]
+    fn test_send_targets() {
+        let metric_addr = "127.0.0.1:60006";
+        let listen_addr = "127.0.0.1:60007";
+        let mut metric_sender = MetricSender::new(metric_addr,
+                                                  String::from(listen_addr),
+                                                  String::from("test-sender"),
+                                                  10,
+                                                  ProcFs::default()).unwrap();
+        metric_sender.add_target(Target::from_str("me=pid:self").unwrap());
+        metric_sender.add_target(Target::from_str("gone=pidfile:/nonexistent.pid").unwrap());
+        let mut listener = Transit::new(listen_addr).unwrap();
+
+        metric_sender.send_targets(listen_addr);
+        let (net_data, _addr): (MetricPacket, _) = listener.recv_from().unwrap();
+        assert_eq!(net_data.target, Some(String::from("me")));
+        match net_data.data {
+            Metric::Process(statm) => assert!(statm.resident > 0),
+            other => panic!("unexpected metric: {:?}", other),
+        }
+        let (net_data, _addr): (MetricPacket, _) = listener.recv_from().unwrap();
+        match net_data.data {
+            Metric::Stat(stat) => assert_eq!(stat.state, 'R'),
+            other => panic!("unexpected metric: {:?}", other),
+        }
+    }
+
     #[test]
     fn test_runner() {
         let metric_addr = "127.0.0.1:60002";
@@ -173,7 +626,8 @@ mod test {
         let metric_sender = Box::new(MetricSender::new(metric_addr,
                                               String::from(listen_addr),
                                               String::from("test-sender"),
-                                              1).unwrap()) as Box<Runner + Send>;
+                                              1,
+                                              ProcFs::default()).unwrap()) as Box<Runner + Send>;
         let mut listener = Transit::new(listen_addr).unwrap();
 
         let (sn, rc) = chan::sync(1);
@@ -0,0 +1,192 @@
+/*
+ * Named processes to monitor. Targets are selected by pid, pidfile, command
+ * name, a regex on the command line or as a process tree, and are resolved
+ * again every cycle, so a restarted service is picked up under its new pid.
+ */
+use std::fmt;
+use std::str::FromStr;
+use std::string::String;
+
+use regex::Regex;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file};
+
+pub enum Selector {
+    Pid(Pid),
+    PidFile(String), // path of a file containing the pid
+    Comm(String), // exact command name, as in /proc/<pid>/comm
+    Cmdline(Regex), // matched against the space joined argv
+    Tree(Pid), // a process and all of its descendants
+}
+
+pub struct Target {
+    pub name: String,
+    pub selector: Selector,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseTargetError {
+    input: String,
+    reason: String,
+}
+
+impl fmt::Display for ParseTargetError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "invalid target '{}': {}", self.input, self.reason)
+    }
+}
+
+// Targets are written NAME=KIND:VALUE, e.g. "web=pidfile:/run/nginx.pid",
+// "db=comm:postgres", "jobs=cmdline:celery.*worker" or "build=tree:1234".
+impl FromStr for Target {
+    type Err = ParseTargetError;
+
+    fn from_str(s: &str) -> Result<Target, ParseTargetError> {
+        let error = |reason: &str| ParseTargetError {
+            input: String::from(s),
+            reason: String::from(reason),
+        };
+
+        let mut name_spec = s.splitn(2, '=');
+        let (name, spec) = match (name_spec.next(), name_spec.next()) {
+            (Some(name), Some(spec)) if !name.is_empty() => (name, spec),
+            _ => return Err(error("expected NAME=KIND:VALUE")),
+        };
+        let mut kind_value = spec.splitn(2, ':');
+        let (kind, value) = match (kind_value.next(), kind_value.next()) {
+            (Some(kind), Some(value)) if !value.is_empty() => (kind, value),
+            _ => return Err(error("expected NAME=KIND:VALUE")),
+        };
+
+        let selector = match kind {
+            "pid" => Selector::Pid(try!(Pid::from_str(value).map_err(|_| error("invalid pid")))),
+            "pidfile" => Selector::PidFile(String::from(value)),
+            "comm" => Selector::Comm(String::from(value)),
+            "cmdline" => Selector::Cmdline(try!(Regex::new(value).map_err(|_| error("invalid regex")))),
+            "tree" => Selector::Tree(try!(Pid::from_str(value).map_err(|_| error("invalid pid")))),
+            _ => return Err(error("kind must be pid, pidfile, comm, cmdline or tree")),
+        };
+
+        Ok(Target {
+            name: String::from(name),
+            selector: selector,
+        })
+    }
+}
+
+impl Target {
+    /// The pids currently matching the target, in pid order. Empty while the
+    /// target is not running.
+    pub fn resolve(&self, proc_fs: &ProcFs) -> Result<Vec<u32>, ProcError> {
+        match self.selector {
+            Selector::Pid(pid) => resolve_pid(proc_fs, pid),
+            Selector::PidFile(ref path) => {
+                let contents = match read_proc_file(path) {
+                    Ok(contents) => contents,
+                    Err(ProcError::NotFound(_)) => return Ok(Vec::new()),
+                    Err(e) => return Err(e),
+                };
+                match Pid::from_str(contents.trim()) {
+                    Ok(pid) => resolve_pid(proc_fs, pid),
+                    Err(_) => Err(ProcError::parse(path, 1, "pid")),
+                }
+            },
+            Selector::Comm(ref comm) => {
+                let processes = try!(proc_fs.processes());
+                Ok(processes.into_iter().filter(|p| p.comm == *comm).map(|p| p.pid).collect())
+            },
+            Selector::Cmdline(ref regex) => {
+                // Our own argv holds the regex, so it would always match.
+                let own = proc_fs.process_stat(Pid::Current).ok().map(|stat| stat.pid as u32);
+                let mut pids = Vec::new();
+                for pid in try!(proc_fs.pids()) {
+                    if Some(pid) == own {
+                        continue
+                    }
+                    match proc_fs.process_cmdline(Pid::Id(pid)) {
+                        Ok(argv) => if regex.is_match(&argv.join(" ")) { pids.push(pid) },
+                        Err(ProcError::NotFound(_)) => continue,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(pids)
+            },
+            Selector::Tree(pid) => match proc_fs.process_tree(pid) {
+                Ok(tree) => {
+                    let mut pids = tree.pids();
+                    pids.sort();
+                    Ok(pids)
+                },
+                Err(ProcError::NotFound(_)) => Ok(Vec::new()),
+                Err(e) => Err(e),
+            },
+        }
+    }
+}
+
+fn resolve_pid(proc_fs: &ProcFs, pid: Pid) -> Result<Vec<u32>, ProcError> {
+    match proc_fs.process_stat(pid) {
+        Ok(stat) => Ok(vec![stat.pid as u32]),
+        Err(ProcError::NotFound(_)) => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use libc;
+    use regex::{self, Regex};
+    use super::{Target, Selector};
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    fn resolve(spec: &str) -> Vec<u32> {
+        Target::from_str(spec).unwrap().resolve(&fixtures()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let target = Target::from_str("web=pidfile:/run/nginx.pid").unwrap();
+        assert_eq!(target.name, "web");
+        match target.selector {
+            Selector::PidFile(ref path) => assert_eq!(path, "/run/nginx.pid"),
+            _ => panic!("expected a pidfile selector"),
+        }
+
+        assert!(Target::from_str("jobs=cmdline:celery.*worker").is_ok());
+        assert!(Target::from_str("web").is_err());
+        assert!(Target::from_str("=pid:1").is_err());
+        assert!(Target::from_str("web=pid:").is_err());
+        assert!(Target::from_str("web=pid:0").is_err());
+        assert!(Target::from_str("web=exe:/bin/sh").is_err());
+        assert!(Target::from_str("web=cmdline:(").is_err());
+    }
+
+    #[test]
+    fn test_resolve_target() {
+        assert_eq!(resolve("init=pid:1"), vec![1]);
+        assert!(resolve("gone=pid:999").is_empty());
+        assert_eq!(resolve("workers=comm:worker"), vec![413, 414]);
+        assert_eq!(resolve("high=cmdline:--queue high"), vec![413]);
+        assert_eq!(resolve("super=cmdline:supervisord"), vec![412]);
+        assert_eq!(resolve("service=tree:412"), vec![412, 413, 414]);
+        assert!(resolve("service=tree:999").is_empty());
+
+        let pidfile = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/run/supervisord.pid");
+        assert_eq!(resolve(&format!("super=pidfile:{}", pidfile)), vec![412]);
+        assert!(resolve("super=pidfile:/nonexistent/supervisord.pid").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_cmdline_skips_ourselves() {
+        let proc_fs = ProcFs::default();
+        let argv = proc_fs.process_cmdline(Pid::Current).unwrap();
+        let target = Target {
+            name: String::from("me"),
+            selector: Selector::Cmdline(Regex::new(&regex::quote(&argv.join(" "))).unwrap()),
+        };
+
+        let pid = unsafe { libc::getpid() } as u32;
+        assert!(!target.resolve(&proc_fs).unwrap().contains(&pid));
+    }
+}
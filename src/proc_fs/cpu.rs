@@ -0,0 +1,141 @@
+/*
+ * CPU utilisation of a process, computed from two /proc/<pid>/stat samples
+ * and the matching /proc/stat totals.
+ */
+use std::thread;
+
+use proc_fs::{ProcFs, Pid, ProcError};
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CpuSample {
+    utime: usize, // process usermode jiffies
+    stime: usize, // process kernel mode jiffies
+    total: usize, // jiffies spent by all cpus in every state
+    cpus: usize, // # of cpus listed in /proc/stat
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProcessCpu {
+    user_percent: f64, // share of all cpus spent in usermode
+    system_percent: f64, // share of all cpus spent in kernel mode
+    total_percent: f64, // user + system
+    cpus: usize,
+    window_ticks: usize, // jiffies elapsed on a single cpu between samples
+}
+
+impl ProcFs {
+    // Sum of the aggregate "cpu" line of /proc/stat along with the number of
+    // per-cpu lines.
+    fn system_cpu_total(&self) -> Result<(usize, usize), ProcError> {
+        let stat = try!(self.system_stat());
+        Ok((stat.total.total(), stat.cpus.len()))
+    }
+
+    pub fn cpu_sample(&self, pid: Pid) -> Result<CpuSample, ProcError> {
+        let stat = try!(self.process_stat(pid));
+        let (total, cpus) = try!(self.system_cpu_total());
+
+        Ok(CpuSample {
+            utime: stat.utime,
+            stime: stat.stime,
+            total: total,
+            cpus: cpus,
+        })
+    }
+
+    /// Takes two samples `window_ms` apart and reports the usage in between.
+    pub fn process_cpu(&self, pid: Pid, window_ms: u32) -> Result<ProcessCpu, ProcError> {
+        let first = try!(self.cpu_sample(pid));
+        thread::sleep_ms(window_ms);
+        let second = try!(self.cpu_sample(pid));
+
+        Ok(cpu_usage(&first, &second))
+    }
+}
+
+/// Computes the utilisation between two samples. Percentages are relative to
+/// the whole machine, so a single busy thread on a 4 cpu host reads 25%.
+pub fn cpu_usage(prev: &CpuSample, cur: &CpuSample) -> ProcessCpu {
+    let elapsed = cur.total.saturating_sub(prev.total);
+    let user = cur.utime.saturating_sub(prev.utime);
+    let system = cur.stime.saturating_sub(prev.stime);
+    let cpus = if cur.cpus == 0 { 1 } else { cur.cpus };
+
+    let percent = |ticks: usize| {
+        if elapsed == 0 { 0.0 } else { 100.0 * ticks as f64 / elapsed as f64 }
+    };
+
+    ProcessCpu {
+        user_percent: percent(user),
+        system_percent: percent(system),
+        total_percent: percent(user + system),
+        cpus: cpus,
+        window_ticks: elapsed / cpus,
+    }
+}
+
+/// Keeps the previous sample around so that a periodic caller gets the usage
+/// since its last call.
+pub struct CpuSampler {
+    proc_fs: ProcFs,
+    pid: Pid,
+    last: Option<CpuSample>,
+}
+
+impl CpuSampler {
+    pub fn new(proc_fs: ProcFs, pid: Pid) -> CpuSampler {
+        CpuSampler {
+            proc_fs: proc_fs,
+            pid: pid,
+            last: None,
+        }
+    }
+
+    /// Returns `None` on the first call, since there is nothing to compare to.
+    pub fn sample(&mut self) -> Result<Option<ProcessCpu>, ProcError> {
+        let cur = try!(self.proc_fs.cpu_sample(self.pid.clone()));
+        let usage = self.last.as_ref().map(|prev| cpu_usage(prev, &cur));
+        self.last = Some(cur);
+        Ok(usage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::cpu::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_cpu_usage() {
+        let prev = CpuSample { utime: 100, stime: 50, total: 10000, cpus: 4 };
+        let cur = CpuSample { utime: 150, stime: 75, total: 10400, cpus: 4 };
+
+        let usage = cpu_usage(&prev, &cur);
+        assert_eq!(usage.user_percent, 12.5);
+        assert_eq!(usage.system_percent, 6.25);
+        assert_eq!(usage.total_percent, 18.75);
+        assert_eq!(usage.window_ticks, 100);
+    }
+
+    #[test]
+    fn test_cpu_sampler() {
+        let mut sampler = CpuSampler::new(ProcFs::default(), Pid::Current);
+        assert_eq!(sampler.sample().unwrap(), None);
+        assert!(sampler.sample().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_process_cpu() {
+        let usage = ProcFs::default().process_cpu(Pid::Current, 10);
+        assert!(usage.is_ok());
+    }
+
+    #[test]
+    fn test_cpu_sample_fixture() {
+        let sample = fixtures().cpu_sample(Pid::Id(1)).unwrap();
+        assert_eq!(sample.utime, 210);
+        assert_eq!(sample.stime, 403);
+        assert_eq!(sample.total, 60377929);
+        assert_eq!(sample.cpus, 4);
+    }
+}
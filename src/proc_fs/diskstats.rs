@@ -0,0 +1,336 @@
+/*
+ * Block device I/O statistics from /proc/diskstats
+ */
+use std::thread;
+
+use time::precise_time_ns;
+
+use proc_fs::{ProcFs, ProcError, read_proc_file, parse_field};
+
+// diskstats counts in 512 byte sectors whatever the device's sector size.
+const SECTOR_SIZE: usize = 512;
+// Major number of loop devices.
+const LOOP_MAJOR: usize = 7;
+
+// Counters since boot, times in milliseconds. Discards came in 4.18 and
+// flushes in 5.5; they are None on older kernels.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct DiskStat {
+    pub major: usize,
+    pub minor: usize,
+    pub name: String,
+    pub reads: usize, // completed
+    pub reads_merged: usize,
+    pub sectors_read: usize,
+    pub read_ms: usize,
+    pub writes: usize,
+    pub writes_merged: usize,
+    pub sectors_written: usize,
+    pub write_ms: usize,
+    pub in_flight: usize, // requests currently in progress
+    pub io_ms: usize, // time with at least one request in flight
+    pub weighted_io_ms: usize, // io time multiplied by the requests in flight
+    pub discards: Option<usize>,
+    pub discards_merged: Option<usize>,
+    pub sectors_discarded: Option<usize>,
+    pub discard_ms: Option<usize>,
+    pub flushes: Option<usize>,
+    pub flush_ms: Option<usize>,
+}
+
+// Activity between two samples of the same device.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct DiskRates {
+    pub reads_per_sec: f64,
+    pub writes_per_sec: f64,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub utilisation_percent: f64, // share of the window the device was busy
+    pub read_latency_ms: f64, // average time per completed read
+    pub write_latency_ms: f64,
+    pub queue_depth: f64, // average requests in flight
+    pub window_secs: f64,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Disk {
+    pub stat: DiskStat,
+    pub partition: bool,
+    pub rates: Option<DiskRates>, // None on the first sample or for a new device
+}
+
+/// Which devices to report. By default every device is kept.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct DiskFilter {
+    pub skip_partitions: bool,
+    pub skip_loop: bool,
+}
+
+impl DiskFilter {
+    pub fn apply(&self, disks: Vec<Disk>) -> Vec<Disk> {
+        disks.into_iter()
+            .filter(|d| !(self.skip_partitions && d.partition))
+            .filter(|d| !(self.skip_loop && d.stat.major == LOOP_MAJOR))
+            .collect()
+    }
+}
+
+pub fn parse_diskstats(path: &str, diskstats: &str) -> Result<Vec<DiskStat>, ProcError> {
+    let mut disks = Vec::new();
+
+    for (i, line) in diskstats.lines().enumerate() {
+        let n = i + 1;
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.is_empty() {
+            continue
+        }
+        if fields.len() < 14 {
+            return Err(ProcError::UnexpectedFormat(
+                format!("{}: {} fields on line {}", path, fields.len(), n)))
+        }
+
+        let name = fields[2];
+        let mut values = Vec::new();
+        for value in fields[3..].iter().take(17) {
+            values.push(try!(parse_field::<usize>(path, n, name, value)));
+        }
+
+        disks.push(DiskStat {
+            major: try!(parse_field(path, n, "major", fields[0])),
+            minor: try!(parse_field(path, n, "minor", fields[1])),
+            name: String::from(name),
+            reads: values[0],
+            reads_merged: values[1],
+            sectors_read: values[2],
+            read_ms: values[3],
+            writes: values[4],
+            writes_merged: values[5],
+            sectors_written: values[6],
+            write_ms: values[7],
+            in_flight: values[8],
+            io_ms: values[9],
+            weighted_io_ms: values[10],
+            discards: values.get(11).cloned(),
+            discards_merged: values.get(12).cloned(),
+            sectors_discarded: values.get(13).cloned(),
+            discard_ms: values.get(14).cloned(),
+            flushes: values.get(15).cloned(),
+            flush_ms: values.get(16).cloned(),
+        });
+    }
+
+    Ok(disks)
+}
+
+// The kernel names partitions after their disk, with a "p" in between when
+// the disk's name ends in a digit: sda1 of sda, nvme0n1p1 of nvme0n1. The
+// major number is no help since partitions past the 15th get major 259.
+fn is_partition_of(name: &str, disk: &str) -> bool {
+    if !name.starts_with(disk) || disk.is_empty() {
+        return false
+    }
+    let mut number = &name[disk.len()..];
+    if disk.ends_with(|c: char| c.is_digit(10)) {
+        if !number.starts_with('p') {
+            return false
+        }
+        number = &number[1..];
+    }
+    !number.is_empty() && number.chars().all(|c| c.is_digit(10))
+}
+
+/// Whether the device is a partition of another listed device, e.g. sda1 of
+/// sda or nvme0n1p1 of nvme0n1, but not dm-10 of dm-1.
+pub fn is_partition(disk: &DiskStat, disks: &[DiskStat]) -> bool {
+    disks.iter().any(|d| is_partition_of(&disk.name, &d.name))
+}
+
+pub fn disk_rates(prev: &DiskStat, cur: &DiskStat, window_secs: f64) -> DiskRates {
+    let delta = |prev: usize, cur: usize| cur.saturating_sub(prev) as f64;
+    let rate = |prev: usize, cur: usize| {
+        if window_secs <= 0.0 { 0.0 } else { delta(prev, cur) / window_secs }
+    };
+    let latency = |ms: f64, ops: f64| if ops == 0.0 { 0.0 } else { ms / ops };
+    let window_ms = window_secs * 1000.0;
+
+    let reads = delta(prev.reads, cur.reads);
+    let writes = delta(prev.writes, cur.writes);
+    let io_ms = delta(prev.io_ms, cur.io_ms);
+
+    DiskRates {
+        reads_per_sec: rate(prev.reads, cur.reads),
+        writes_per_sec: rate(prev.writes, cur.writes),
+        read_bytes_per_sec: rate(prev.sectors_read, cur.sectors_read) * SECTOR_SIZE as f64,
+        write_bytes_per_sec: rate(prev.sectors_written, cur.sectors_written) * SECTOR_SIZE as f64,
+        utilisation_percent: if window_ms <= 0.0 { 0.0 } else { (100.0 * io_ms / window_ms).min(100.0) },
+        read_latency_ms: latency(delta(prev.read_ms, cur.read_ms), reads),
+        write_latency_ms: latency(delta(prev.write_ms, cur.write_ms), writes),
+        queue_depth: if window_ms <= 0.0 { 0.0 } else {
+            delta(prev.weighted_io_ms, cur.weighted_io_ms) / window_ms
+        },
+        window_secs: window_secs,
+    }
+}
+
+/// Pairs every current device with its previous sample by name.
+pub fn disks(prev: Option<&[DiskStat]>, cur: Vec<DiskStat>, window_secs: f64) -> Vec<Disk> {
+    cur.iter().map(|stat| {
+        let rates = prev
+            .and_then(|prev| prev.iter().find(|p| p.name == stat.name))
+            .map(|p| disk_rates(p, stat, window_secs));
+        Disk {
+            stat: stat.clone(),
+            partition: is_partition(stat, &cur),
+            rates: rates,
+        }
+    }).collect()
+}
+
+impl ProcFs {
+    pub fn diskstats(&self) -> Result<Vec<DiskStat>, ProcError> {
+        let path = self.path("diskstats");
+        let diskstats = try!(read_proc_file(&path));
+
+        parse_diskstats(&path, &diskstats)
+    }
+
+    /// Takes two samples `window_ms` apart and reports the matching devices
+    /// with their activity in between.
+    pub fn disks(&self, filter: DiskFilter, window_ms: u32) -> Result<Vec<Disk>, ProcError> {
+        let first = try!(self.diskstats());
+        let start = precise_time_ns();
+        thread::sleep_ms(window_ms);
+        let second = try!(self.diskstats());
+        let window_secs = (precise_time_ns() - start) as f64 / 1e9;
+
+        Ok(filter.apply(disks(Some(&first), second, window_secs)))
+    }
+}
+
+/// Keeps the previous sample around so that a periodic caller gets the
+/// activity since its last call.
+pub struct DiskSampler {
+    proc_fs: ProcFs,
+    filter: DiskFilter,
+    last: Option<(Vec<DiskStat>, u64)>, // with the precise_time_ns it was taken at
+}
+
+impl DiskSampler {
+    pub fn new(proc_fs: ProcFs, filter: DiskFilter) -> DiskSampler {
+        DiskSampler {
+            proc_fs: proc_fs,
+            filter: filter,
+            last: None,
+        }
+    }
+
+    pub fn sample(&mut self) -> Result<Vec<Disk>, ProcError> {
+        let cur = try!(self.proc_fs.diskstats());
+        let now = precise_time_ns();
+        let result = match self.last {
+            Some((ref prev, then)) => disks(Some(prev), cur.clone(), (now - then) as f64 / 1e9),
+            None => disks(None, cur.clone(), 0.0),
+        };
+        self.last = Some((cur, now));
+        Ok(self.filter.apply(result))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::diskstats::*;
+    use proc_fs::{ProcFs, fixtures};
+
+    #[test]
+    fn test_diskstats() {
+        assert!(ProcFs::default().diskstats().is_ok());
+    }
+
+    #[test]
+    fn test_diskstats_fixture() {
+        let stats = fixtures().diskstats().unwrap();
+        assert_eq!(stats.len(), 10);
+
+        let sda = &stats[1];
+        assert_eq!((sda.major, sda.minor), (8, 0));
+        assert_eq!(sda.name, "sda");
+        assert_eq!(sda.reads, 1204511);
+        assert_eq!(sda.sectors_read, 98310232);
+        assert_eq!(sda.write_ms, 9120334);
+        assert_eq!(sda.in_flight, 2);
+        assert_eq!(sda.weighted_io_ms, 9943438);
+        assert_eq!(sda.discards, Some(1200));
+        assert_eq!(sda.sectors_discarded, Some(2048000));
+        assert_eq!(sda.flushes, Some(98210));
+        assert_eq!(sda.flush_ms, Some(40212));
+    }
+
+    #[test]
+    fn test_parse_diskstats_old_kernel() {
+        let stats = parse_diskstats("diskstats", "   8       0 sda 1 0 8 2 3 0 24 5 0 7 7\n").unwrap();
+        assert_eq!(stats[0].writes, 3);
+        assert_eq!(stats[0].discards, None);
+        assert_eq!(stats[0].flushes, None);
+
+        assert!(parse_diskstats("diskstats", "   8       0 sda 1 0 8\n").is_err());
+        assert!(parse_diskstats("diskstats", "   8       0 sda 1 0 8 2 3 0 24 5 0 7 x\n").is_err());
+    }
+
+    #[test]
+    fn test_disk_filter() {
+        let all = disks(None, fixtures().diskstats().unwrap(), 0.0);
+        let partitions = all.iter().filter(|d| d.partition).map(|d| d.stat.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(partitions, vec!["sda1", "sda2", "nvme0n1p1"]);
+
+        let filter = DiskFilter { skip_partitions: true, skip_loop: true };
+        let names = filter.apply(all.clone()).into_iter().map(|d| d.stat.name).collect::<Vec<String>>();
+        assert_eq!(names, vec!["sda", "nvme0n1", "nvme0n10", "dm-0", "dm-1", "dm-10"]);
+        assert_eq!(DiskFilter::default().apply(all).len(), 10);
+    }
+
+    #[test]
+    fn test_is_partition_naming() {
+        let disk = |name: &str| DiskStat { name: String::from(name), ..DiskStat::default() };
+        let disks = vec![disk("sda"), disk("sda1"), disk("mmcblk0"), disk("mmcblk0p2"),
+                         disk("md1"), disk("md10"), disk("sdab")];
+
+        assert!(is_partition(&disks[1], &disks));
+        assert!(is_partition(&disks[3], &disks));
+        assert!(!is_partition(&disks[0], &disks));
+        assert!(!is_partition(&disks[5], &disks)); // md10 is not md1 + "0"
+        assert!(!is_partition(&disks[6], &disks)); // sdab is a disk, not sda + "b"
+    }
+
+    #[test]
+    fn test_disk_rates() {
+        let prev = DiskStat {
+            reads: 100, sectors_read: 800, read_ms: 50,
+            writes: 10, sectors_written: 80, write_ms: 40,
+            io_ms: 1000, weighted_io_ms: 2000,
+            ..DiskStat::default()
+        };
+        let cur = DiskStat {
+            reads: 300, sectors_read: 4800, read_ms: 450,
+            writes: 10, sectors_written: 80, write_ms: 40,
+            io_ms: 1500, weighted_io_ms: 4000,
+            ..DiskStat::default()
+        };
+
+        let rates = disk_rates(&prev, &cur, 2.0);
+        assert_eq!(rates.reads_per_sec, 100.0);
+        assert_eq!(rates.read_bytes_per_sec, 1024000.0);
+        assert_eq!(rates.writes_per_sec, 0.0);
+        assert_eq!(rates.utilisation_percent, 25.0);
+        assert_eq!(rates.read_latency_ms, 2.0);
+        assert_eq!(rates.write_latency_ms, 0.0);
+        assert_eq!(rates.queue_depth, 1.0);
+    }
+
+    #[test]
+    fn test_disk_sampler() {
+        let mut sampler = DiskSampler::new(ProcFs::default(), DiskFilter::default());
+        assert!(sampler.sample().unwrap().iter().all(|d| d.rates.is_none()));
+        assert!(sampler.sample().unwrap().iter().all(|d| d.rates.is_some()));
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * Open file descriptors of a process from /proc/<pid>/fd and
+ * /proc/<pid>/fdinfo
+ */
+use std::fs;
+use std::string::String;
+use std::str::FromStr;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field};
+use proc_fs::limits::{find_limit, MAX_OPEN_FILES};
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum FdKind {
+    File,
+    Socket,
+    Pipe,
+    EventFd,
+    EventPoll,
+    TimerFd,
+    AnonInode, // Any other anon_inode, e.g. inotify or signalfd
+    Device,
+    Unknown,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FileDescriptor {
+    pub fd: usize,
+    pub target: String, // link target, e.g. "/var/log/app.log" or "socket:[1234]"
+    pub kind: FdKind,
+    pub pos: Option<usize>, // file offset, from fdinfo
+    pub flags: Option<usize>, // open(2) flags, from fdinfo
+}
+
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct FdCounts {
+    pub total: usize,
+    pub files: usize,
+    pub sockets: usize,
+    pub pipes: usize,
+    pub eventfds: usize,
+    pub eventpolls: usize,
+    pub timerfds: usize,
+    pub anon_inodes: usize,
+    pub devices: usize,
+    pub unknown: usize,
+    pub soft_limit: Option<usize>, // RLIMIT_NOFILE, None if unlimited
+    pub limit_percent: Option<f64>, // total as a percentage of soft_limit
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FdInventory {
+    pub fds: Vec<FileDescriptor>,
+    pub counts: FdCounts,
+}
+
+pub fn classify(target: &str) -> FdKind {
+    if target.starts_with("socket:") {
+        FdKind::Socket
+    } else if target.starts_with("pipe:") {
+        FdKind::Pipe
+    } else if target.starts_with("anon_inode:") {
+        match target.trim_left_matches("anon_inode:") {
+            "[eventfd]" => FdKind::EventFd,
+            "[eventpoll]" => FdKind::EventPoll,
+            "[timerfd]" => FdKind::TimerFd,
+            _ => FdKind::AnonInode,
+        }
+    } else if target.starts_with("/dev/") {
+        FdKind::Device
+    } else if target.starts_with("/") {
+        FdKind::File
+    } else {
+        FdKind::Unknown
+    }
+}
+
+pub fn count_fds(fds: &[FileDescriptor], soft_limit: Option<usize>) -> FdCounts {
+    let mut counts = FdCounts::default();
+    for fd in fds.iter() {
+        counts.total += 1;
+        match fd.kind {
+            FdKind::File => counts.files += 1,
+            FdKind::Socket => counts.sockets += 1,
+            FdKind::Pipe => counts.pipes += 1,
+            FdKind::EventFd => counts.eventfds += 1,
+            FdKind::EventPoll => counts.eventpolls += 1,
+            FdKind::TimerFd => counts.timerfds += 1,
+            FdKind::AnonInode => counts.anon_inodes += 1,
+            FdKind::Device => counts.devices += 1,
+            FdKind::Unknown => counts.unknown += 1,
+        }
+    }
+
+    let total = counts.total;
+    counts.soft_limit = soft_limit;
+    counts.limit_percent = soft_limit.map(|limit| {
+        if limit == 0 { 100.0 } else { 100.0 * total as f64 / limit as f64 }
+    });
+    counts
+}
+
+// fdinfo contains at least "pos:" and "flags:" (octal) lines.
+fn parse_fdinfo(path: &str, fdinfo: &str) -> Result<(Option<usize>, Option<usize>), ProcError> {
+    let mut pos = None;
+    let mut flags = None;
+
+    for (i, line) in fdinfo.lines().enumerate() {
+        let mut kv = line.splitn(2, ':');
+        match (kv.next(), kv.next()) {
+            (Some("pos"), Some(v)) => pos = Some(try!(parse_field(path, i + 1, "pos", v.trim()))),
+            (Some("flags"), Some(v)) => {
+                flags = Some(try!(usize::from_str_radix(v.trim(), 8)
+                                  .map_err(|_| ProcError::parse(path, i + 1, "flags"))));
+            },
+            _ => {},
+        }
+    }
+
+    Ok((pos, flags))
+}
+
+impl ProcFs {
+    /// Every open descriptor of the process. Descriptors closed while we are
+    /// reading the directory are skipped.
+    pub fn process_fds(&self, pid: Pid) -> Result<Vec<FileDescriptor>, ProcError> {
+        let dir = self.path(&format!("{}/fd", pid));
+        let entries = try!(fs::read_dir(&dir).map_err(|e| ProcError::from_io(&dir, e)));
+
+        let mut fds = Vec::new();
+        for entry in entries {
+            let entry = try!(entry.map_err(|e| ProcError::from_io(&dir, e)));
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let fd = match usize::from_str(&name) {
+                Ok(fd) => fd,
+                Err(_) => continue,
+            };
+
+            let target = match fs::read_link(entry.path()) {
+                Ok(target) => target.to_string_lossy().into_owned(),
+                Err(e) => match ProcError::from_io(&dir, e) {
+                    ProcError::NotFound(_) => continue,
+                    err => return Err(err),
+                },
+            };
+
+            let info_path = self.path(&format!("{}/fdinfo/{}", pid, fd));
+            let (pos, flags) = match read_proc_file(&info_path) {
+                Ok(info) => try!(parse_fdinfo(&info_path, &info)),
+                Err(ProcError::NotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+
+            fds.push(FileDescriptor {
+                fd: fd,
+                kind: classify(&target),
+                target: target,
+                pos: pos,
+                flags: flags,
+            });
+        }
+
+        fds.sort_by(|a, b| a.fd.cmp(&b.fd));
+        Ok(fds)
+    }
+
+    // Soft limit on open files, RLIMIT_NOFILE.
+    fn open_files_limit(&self, pid: Pid) -> Result<Option<usize>, ProcError> {
+        let limits = try!(self.process_limits(pid));
+        match find_limit(&limits, MAX_OPEN_FILES) {
+            Some(limit) => Ok(limit.soft),
+            None => Err(ProcError::UnexpectedFormat(
+                    format!("{}: no {} line", self.path(&format!("{}/limits", pid)), MAX_OPEN_FILES))),
+        }
+    }
+
+    pub fn process_fd_counts(&self, pid: Pid) -> Result<FdCounts, ProcError> {
+        let fds = try!(self.process_fds(pid));
+        let limit = try!(self.open_files_limit(pid));
+        Ok(count_fds(&fds, limit))
+    }
+
+    pub fn process_fd_inventory(&self, pid: Pid) -> Result<FdInventory, ProcError> {
+        let fds = try!(self.process_fds(pid));
+        let limit = try!(self.open_files_limit(pid));
+        let counts = count_fds(&fds, limit);
+
+        Ok(FdInventory {
+            fds: fds,
+            counts: counts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::fds::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("/var/log/app.log"), FdKind::File);
+        assert_eq!(classify("/dev/null"), FdKind::Device);
+        assert_eq!(classify("socket:[12345]"), FdKind::Socket);
+        assert_eq!(classify("pipe:[6789]"), FdKind::Pipe);
+        assert_eq!(classify("anon_inode:[eventfd]"), FdKind::EventFd);
+        assert_eq!(classify("anon_inode:[eventpoll]"), FdKind::EventPoll);
+        assert_eq!(classify("anon_inode:[timerfd]"), FdKind::TimerFd);
+        assert_eq!(classify("anon_inode:inotify"), FdKind::AnonInode);
+        assert_eq!(classify("net:[4026531992]"), FdKind::Unknown);
+    }
+
+    #[test]
+    fn test_proc_fds() {
+        let proc_fs = ProcFs::default();
+        let inventory = proc_fs.process_fd_inventory(Pid::Current).unwrap();
+        assert!(inventory.counts.total > 0);
+        assert_eq!(inventory.counts.total, inventory.fds.len());
+    }
+
+    #[test]
+    fn test_proc_fds_fixture() {
+        let inventory = fixtures().process_fd_inventory(Pid::Id(1)).unwrap();
+        assert_eq!(inventory.fds.len(), 7);
+        assert_eq!(inventory.fds[0].target, "/dev/null");
+        assert_eq!(inventory.fds[6].kind, FdKind::File);
+        assert_eq!(inventory.fds[6].pos, Some(4096));
+        assert_eq!(inventory.fds[6].flags, Some(0o102001));
+
+        let counts = inventory.counts;
+        assert_eq!(counts.total, 7);
+        assert_eq!(counts.devices, 1);
+        assert_eq!(counts.sockets, 1);
+        assert_eq!(counts.pipes, 1);
+        assert_eq!(counts.eventpolls, 1);
+        assert_eq!(counts.eventfds, 1);
+        assert_eq!(counts.timerfds, 1);
+        assert_eq!(counts.files, 1);
+        assert_eq!(counts.soft_limit, Some(1024));
+        assert!(counts.limit_percent.unwrap() > 0.68 && counts.limit_percent.unwrap() < 0.69);
+    }
+}
@@ -0,0 +1,379 @@
+/*
+ * Mounted filesystems from /proc/<pid>/mountinfo, with their capacity and
+ * inode usage from statvfs(3).
+ */
+use std::ffi::CString;
+use std::io;
+use std::mem;
+
+use libc;
+use regex::Regex;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field};
+
+// libc does not bind statvfs yet. This is the glibc layout, where the block
+// and file counts are unsigned longs unless _FILE_OFFSET_BITS=64.
+#[repr(C)]
+#[allow(non_camel_case_types, dead_code)]
+struct statvfs {
+    f_bsize: libc::c_ulong,
+    f_frsize: libc::c_ulong,
+    f_blocks: libc::c_ulong,
+    f_bfree: libc::c_ulong,
+    f_bavail: libc::c_ulong,
+    f_files: libc::c_ulong,
+    f_ffree: libc::c_ulong,
+    f_favail: libc::c_ulong,
+    f_fsid: libc::c_ulong,
+    #[cfg(target_pointer_width = "32")]
+    __f_unused: libc::c_int,
+    f_flag: libc::c_ulong,
+    f_namemax: libc::c_ulong,
+    __f_spare: [libc::c_int; 6],
+}
+
+extern "C" {
+    fn statvfs(path: *const libc::c_char, buf: *mut statvfs) -> libc::c_int;
+}
+
+// Kernel interfaces rather than storage. tmpfs and overlay are kept since
+// they can fill up too.
+const PSEUDO_FILESYSTEMS: [&'static str; 21] = [
+    "autofs", "binfmt_misc", "bpf", "cgroup", "cgroup2", "configfs", "debugfs",
+    "devpts", "efivarfs", "fusectl", "hugetlbfs", "mqueue", "nsfs", "proc",
+    "pstore", "rpc_pipefs", "securityfs", "selinuxfs", "sysfs", "tracefs",
+    "devtmpfs",
+];
+
+// statvfs(3) on these blocks for as long as the server does not answer, so
+// they are only reported when asked for by type.
+const NETWORK_FILESYSTEMS: [&'static str; 11] = [
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "ceph", "glusterfs", "9p", "afs",
+    "lustre", "fuse",
+];
+
+fn is_network_filesystem(fs_type: &str) -> bool {
+    NETWORK_FILESYSTEMS.contains(&fs_type) || fs_type.starts_with("fuse.")
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Mount {
+    pub mount_id: usize,
+    pub parent_id: usize,
+    pub major: usize,
+    pub minor: usize,
+    pub root: String, // directory of the filesystem mounted, "/" unless a bind mount
+    pub mount_point: String, // relative to the process's root
+    pub options: Vec<String>, // per mount, e.g. "ro" or "noatime"
+    pub fs_type: String,
+    pub source: String, // e.g. "/dev/sda1", or "none"
+    pub super_options: Vec<String>, // per filesystem
+}
+
+// Sizes in bytes.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct FsStats {
+    pub block_size: usize,
+    pub blocks: usize,
+    pub blocks_free: usize,
+    pub blocks_available: usize, // free to unprivileged users
+    pub files: usize,
+    pub files_free: usize,
+    pub files_available: usize,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Filesystem {
+    pub mount_point: String,
+    pub source: String,
+    pub fs_type: String,
+    pub options: Vec<String>,
+    pub size: usize, // bytes
+    pub used: usize,
+    pub available: usize, // to unprivileged users
+    pub used_percent: f64, // of what unprivileged users can use, as df reports it
+    pub inodes: usize,
+    pub inodes_used: usize,
+    pub inodes_available: usize,
+    pub inodes_used_percent: f64,
+}
+
+/// Which filesystems to report. With neither set every real filesystem is
+/// kept, except network and FUSE filesystems which have to be named in
+/// `fs_types`.
+#[derive(Clone, Debug, Default)]
+pub struct FilesystemFilter {
+    pub fs_types: Vec<String>, // e.g. ext4 and xfs, empty for any
+    pub mount_point: Option<Regex>,
+}
+
+impl FilesystemFilter {
+    pub fn matches(&self, mount: &Mount) -> bool {
+        let type_matches = if self.fs_types.is_empty() {
+            !is_network_filesystem(&mount.fs_type)
+        } else {
+            self.fs_types.iter().any(|t| *t == mount.fs_type)
+        };
+        type_matches &&
+            self.mount_point.as_ref().map_or(true, |r| r.is_match(&mount.mount_point))
+    }
+}
+
+// Spaces, tabs, newlines and backslashes in paths are written as octal
+// escapes, e.g. "\040".
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = if bytes[i] == b'\\' && i + 4 <= bytes.len() &&
+            bytes[i + 1..i + 4].iter().all(|b| *b >= b'0' && *b <= b'7') {
+            let value = bytes[i + 1..i + 4].iter().fold(0u16, |v, b| v * 8 + (*b - b'0') as u16);
+            if value <= 0xff { Some(value as u8) } else { None }
+        } else {
+            None
+        };
+        match octal {
+            Some(b) => {
+                unescaped.push(b);
+                i += 4;
+            },
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+fn options(field: &str) -> Vec<String> {
+    field.split(',').map(String::from).collect()
+}
+
+// 36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+// The optional fields before the "-" vary in number.
+pub fn parse_mountinfo(path: &str, mountinfo: &str) -> Result<Vec<Mount>, ProcError> {
+    let mut mounts = Vec::new();
+
+    for (i, line) in mountinfo.lines().enumerate() {
+        let n = i + 1;
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.is_empty() {
+            continue
+        }
+        let separator = match fields.iter().position(|f| *f == "-") {
+            Some(separator) if separator >= 6 && fields.len() >= separator + 4 => separator,
+            _ => return Err(ProcError::UnexpectedFormat(
+                format!("{}: malformed mount on line {}", path, n))),
+        };
+
+        let mut device = fields[2].splitn(2, ':');
+        let (major, minor) = match (device.next(), device.next()) {
+            (Some(major), Some(minor)) => (try!(parse_field(path, n, "major", major)),
+                                           try!(parse_field(path, n, "minor", minor))),
+            _ => return Err(ProcError::parse(path, n, "major:minor")),
+        };
+
+        mounts.push(Mount {
+            mount_id: try!(parse_field(path, n, "mount_id", fields[0])),
+            parent_id: try!(parse_field(path, n, "parent_id", fields[1])),
+            major: major,
+            minor: minor,
+            root: unescape(fields[3]),
+            mount_point: unescape(fields[4]),
+            options: options(fields[5]),
+            fs_type: String::from(fields[separator + 1]),
+            source: unescape(fields[separator + 2]),
+            super_options: options(fields[separator + 3]),
+        });
+    }
+
+    Ok(mounts)
+}
+
+/// Capacity of the filesystem holding `path`.
+pub fn fs_stats(path: &str) -> Result<FsStats, ProcError> {
+    let c_path = try!(CString::new(path).map_err(|_| ProcError::parse(path, 0, "path")));
+    let buf = unsafe {
+        let mut buf: statvfs = mem::zeroed();
+        if statvfs(c_path.as_ptr(), &mut buf) != 0 {
+            return Err(ProcError::from_io(path, io::Error::last_os_error()))
+        }
+        buf
+    };
+
+    Ok(FsStats {
+        block_size: buf.f_frsize as usize,
+        blocks: buf.f_blocks as usize,
+        blocks_free: buf.f_bfree as usize,
+        blocks_available: buf.f_bavail as usize,
+        files: buf.f_files as usize,
+        files_free: buf.f_ffree as usize,
+        files_available: buf.f_favail as usize,
+    })
+}
+
+pub fn filesystem(mount: &Mount, stats: &FsStats) -> Filesystem {
+    let used = stats.blocks.saturating_sub(stats.blocks_free) * stats.block_size;
+    let available = stats.blocks_available * stats.block_size;
+    let inodes_used = stats.files.saturating_sub(stats.files_free);
+    let percent = |part: usize, whole: usize| {
+        if whole == 0 { 0.0 } else { 100.0 * part as f64 / whole as f64 }
+    };
+
+    Filesystem {
+        mount_point: mount.mount_point.clone(),
+        source: mount.source.clone(),
+        fs_type: mount.fs_type.clone(),
+        options: mount.options.clone(),
+        size: stats.blocks * stats.block_size,
+        used: used,
+        available: available,
+        used_percent: percent(used, used + available),
+        inodes: stats.files,
+        inodes_used: inodes_used,
+        inodes_available: stats.files_available,
+        inodes_used_percent: percent(inodes_used, stats.files),
+    }
+}
+
+impl ProcFs {
+    /// Mounts in the mount namespace of the process.
+    pub fn process_mountinfo(&self, pid: Pid) -> Result<Vec<Mount>, ProcError> {
+        let path = self.path(&format!("{}/mountinfo", pid));
+        let mountinfo = try!(read_proc_file(&path));
+
+        parse_mountinfo(&path, &mountinfo)
+    }
+
+    /// Capacity of the real filesystems the process sees that match
+    /// `filter`. They are looked up through /proc/<pid>/root, so this works
+    /// for processes in another mount namespace as long as we may follow
+    /// that link. Mounts that have gone or that we may not look at are
+    /// skipped, and so are broken ones, e.g. a stale NFS handle, with a
+    /// warning.
+    pub fn filesystems(&self, pid: Pid, filter: &FilesystemFilter) -> Result<Vec<Filesystem>, ProcError> {
+        let mounts = try!(self.process_mountinfo(pid));
+
+        let mut filesystems = Vec::new();
+        for mount in mounts.iter() {
+            if PSEUDO_FILESYSTEMS.contains(&mount.fs_type.as_str()) || !filter.matches(mount) {
+                continue
+            }
+            let path = self.path(&format!("{}/root{}", pid, mount.mount_point));
+            let stats = match fs_stats(&path) {
+                Ok(stats) => stats,
+                Err(ProcError::NotFound(_)) | Err(ProcError::PermissionDenied(_)) => continue,
+                Err(e) => {
+                    warn!("Skipping {} filesystem at {}: {}", mount.fs_type, mount.mount_point, e);
+                    continue
+                },
+            };
+            // Anything else without blocks is a pseudo filesystem too.
+            if stats.blocks > 0 {
+                filesystems.push(filesystem(mount, &stats));
+            }
+        }
+        Ok(filesystems)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use regex::Regex;
+    use proc_fs::filesystems::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_filesystems() {
+        let filesystems = ProcFs::default().filesystems(Pid::Current, &FilesystemFilter::default()).unwrap();
+        assert!(filesystems.iter().all(|f| f.size > 0 && f.used <= f.size));
+    }
+
+    #[test]
+    fn test_mountinfo_fixture() {
+        let mounts = fixtures().process_mountinfo(Pid::Id(1)).unwrap();
+        assert_eq!(mounts.len(), 8);
+
+        let root = &mounts[0];
+        assert_eq!(root.mount_id, 22);
+        assert_eq!((root.major, root.minor), (8, 1));
+        assert_eq!(root.mount_point, "/");
+        assert_eq!(root.options, vec!["rw", "relatime"]);
+        assert_eq!(root.fs_type, "ext4");
+        assert_eq!(root.source, "/dev/sda1");
+        assert_eq!(root.super_options, vec!["rw", "errors=remount-ro"]);
+
+        // two optional fields before the separator
+        assert_eq!(mounts[5].fs_type, "xfs");
+        assert_eq!(mounts[5].source, "/dev/nvme0n1p1");
+
+        assert_eq!(mounts[6].root, "/backups");
+        assert_eq!(mounts[6].mount_point, "/mnt/backup disk");
+    }
+
+    #[test]
+    fn test_network_filesystems_named_only() {
+        let mounts = fixtures().process_mountinfo(Pid::Id(1)).unwrap();
+        let nfs = &mounts[7];
+        assert_eq!(nfs.fs_type, "nfs4");
+        assert!(!FilesystemFilter::default().matches(nfs));
+
+        let filter = FilesystemFilter {
+            fs_types: vec![String::from("nfs4")],
+            mount_point: None,
+        };
+        assert!(filter.matches(nfs));
+        assert!(!filter.matches(&mounts[0]));
+    }
+
+    #[test]
+    fn test_parse_mountinfo_malformed() {
+        assert!(parse_mountinfo("mountinfo", "22 1 8:1 / / rw - ext4\n").is_err());
+        assert!(parse_mountinfo("mountinfo", "22 1 8:1 / / rw shared:1\n").is_err());
+        assert!(parse_mountinfo("mountinfo", "22 1 8 / / rw - ext4 /dev/sda1 rw\n").is_err());
+    }
+
+    #[test]
+    fn test_filesystems_fixture() {
+        // fixtures/proc/1/root links to /, where the backup disk is not mounted
+        let filter = FilesystemFilter {
+            fs_types: vec![String::from("ext4")],
+            mount_point: None,
+        };
+        let filesystems = fixtures().filesystems(Pid::Id(1), &filter).unwrap();
+        assert_eq!(filesystems.len(), 1);
+        assert_eq!(filesystems[0].mount_point, "/");
+        assert_eq!(filesystems[0].source, "/dev/sda1");
+        assert!(filesystems[0].size > 0);
+
+        let filter = FilesystemFilter {
+            fs_types: Vec::new(),
+            mount_point: Some(Regex::new("^/(proc|sys)").unwrap()),
+        };
+        assert!(fixtures().filesystems(Pid::Id(1), &filter).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_filesystem_usage() {
+        let mounts = parse_mountinfo("mountinfo", "22 1 8:1 / / rw - ext4 /dev/sda1 rw\n").unwrap();
+        let stats = FsStats {
+            block_size: 4096,
+            blocks: 1000,
+            blocks_free: 300,
+            blocks_available: 200,
+            files: 500,
+            files_free: 400,
+            files_available: 400,
+        };
+
+        let fs = filesystem(&mounts[0], &stats);
+        assert_eq!(fs.size, 4096000);
+        assert_eq!(fs.used, 2867200);
+        assert_eq!(fs.available, 819200);
+        assert_eq!(fs.used_percent, 77.77777777777777);
+        assert_eq!(fs.inodes_used, 100);
+        assert_eq!(fs.inodes_used_percent, 20.0);
+    }
+}
@@ -0,0 +1,197 @@
+/*
+ * What a process is: its command name and argv from /proc/<pid>/comm and
+ * /proc/<pid>/cmdline, the exe, cwd and root links, and optionally its
+ * environment from /proc/<pid>/environ.
+ */
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{Hash, Hasher, SipHasher};
+use std::string::String;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, read_proc_bytes};
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProcIdentity {
+    pub command: String, // from comm, truncated by the kernel to 15 bytes
+    pub argv: Vec<String>, // empty for kernel threads and zombies
+    pub argv_hash: u64,
+    pub exe: Option<String>, // None if the link is unreadable or missing
+    pub cwd: Option<String>,
+    pub root: Option<String>,
+    pub environ: Option<BTreeMap<String, String>>, // only read when asked for
+}
+
+/// Environment variables whose names contain one of these, ignoring case,
+/// have their value replaced by REDACTED.
+pub const DEFAULT_REDACT: &'static [&'static str] =
+    &["PASSWORD", "PASSWD", "SECRET", "TOKEN", "KEY", "CREDENTIAL", "AUTH"];
+
+pub const REDACTED: &'static str = "<redacted>";
+
+/// Splits a NUL separated file such as cmdline or environ. The last entry is
+/// NUL terminated too, so there is no trailing empty entry.
+pub fn split_nul(contents: &[u8]) -> Vec<String> {
+    let contents = if contents.last() == Some(&0) {
+        &contents[..contents.len() - 1]
+    } else {
+        contents
+    };
+    if contents.is_empty() {
+        return Vec::new()
+    }
+
+    contents.split(|b| *b == 0)
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+/// Stable across runs and hosts, so equal command lines hash equally.
+pub fn argv_hash(argv: &[String]) -> u64 {
+    let mut hasher = SipHasher::new();
+    argv.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn redact_environ(entries: Vec<String>, redact: &[String]) -> BTreeMap<String, String> {
+    let redact = redact.iter().map(|r| r.to_uppercase()).collect::<Vec<String>>();
+    let mut environ = BTreeMap::new();
+
+    for entry in entries.into_iter() {
+        let mut kv = entry.splitn(2, '=');
+        let (key, value) = match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) => (String::from(k), String::from(v)),
+            (Some(k), None) => (String::from(k), String::new()),
+            _ => continue,
+        };
+
+        let upper = key.to_uppercase();
+        if redact.iter().any(|r| !r.is_empty() && upper.contains(r.as_str())) {
+            environ.insert(key, String::from(REDACTED));
+        } else {
+            environ.insert(key, value);
+        }
+    }
+
+    environ
+}
+
+impl ProcFs {
+    pub fn process_comm(&self, pid: Pid) -> Result<String, ProcError> {
+        let path = self.path(&format!("{}/comm", pid));
+        let comm = try!(read_proc_file(&path));
+        Ok(String::from(comm.trim_right_matches('\n')))
+    }
+
+    pub fn process_cmdline(&self, pid: Pid) -> Result<Vec<String>, ProcError> {
+        let path = self.path(&format!("{}/cmdline", pid));
+        let cmdline = try!(read_proc_bytes(&path));
+        Ok(split_nul(&cmdline))
+    }
+
+    /// The environment the process was started with, with values of
+    /// variables matching `redact` hidden.
+    pub fn process_environ(&self, pid: Pid, redact: &[String])
+        -> Result<BTreeMap<String, String>, ProcError> {
+        let path = self.path(&format!("{}/environ", pid));
+        let environ = try!(read_proc_bytes(&path));
+        Ok(redact_environ(split_nul(&environ), redact))
+    }
+
+    // exe, cwd and root cannot be read for other users' processes, and exe
+    // does not exist for kernel threads.
+    fn process_link(&self, pid: Pid, name: &str) -> Result<Option<String>, ProcError> {
+        let path = self.path(&format!("{}/{}", pid, name));
+        match fs::read_link(&path) {
+            Ok(target) => Ok(Some(target.to_string_lossy().into_owned())),
+            Err(e) => match ProcError::from_io(&path, e) {
+                ProcError::NotFound(_) | ProcError::PermissionDenied(_) => Ok(None),
+                err => Err(err),
+            },
+        }
+    }
+
+    /// Identity of the process. The environment is only read when a
+    /// redaction list is given.
+    pub fn process_identity(&self, pid: Pid, redact: Option<&[String]>)
+        -> Result<ProcIdentity, ProcError> {
+        let command = try!(self.process_comm(pid));
+        let argv = try!(self.process_cmdline(pid));
+        let environ = match redact {
+            Some(redact) => match self.process_environ(pid, redact) {
+                Ok(environ) => Some(environ),
+                Err(ProcError::PermissionDenied(_)) => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        Ok(ProcIdentity {
+            command: command,
+            argv_hash: argv_hash(&argv),
+            argv: argv,
+            exe: try!(self.process_link(pid, "exe")),
+            cwd: try!(self.process_link(pid, "cwd")),
+            root: try!(self.process_link(pid, "root")),
+            environ: environ,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use proc_fs::identity::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_split_nul() {
+        assert_eq!(split_nul(b"a\0b c\0"), vec!["a", "b c"]);
+        assert_eq!(split_nul(b"a\0\0b"), vec!["a", "", "b"]);
+        assert!(split_nul(b"").is_empty());
+    }
+
+    #[test]
+    fn test_redact_environ() {
+        let entries = vec![String::from("HOME=/root"),
+                           String::from("GITHUB_TOKEN=abc"),
+                           String::from("EMPTY=")];
+        let environ = redact_environ(entries, &[String::from("token")]);
+        assert_eq!(environ["HOME"], "/root");
+        assert_eq!(environ["GITHUB_TOKEN"], REDACTED);
+        assert_eq!(environ["EMPTY"], "");
+    }
+
+    #[test]
+    fn test_proc_identity() {
+        let proc_fs = ProcFs::default();
+        let redact: Vec<String> = Vec::new();
+        let identity = proc_fs.process_identity(Pid::Current, Some(&redact[..])).unwrap();
+        assert_eq!(identity.argv, env::args().collect::<Vec<String>>());
+        assert_eq!(identity.argv_hash, argv_hash(&identity.argv));
+        assert!(identity.exe.is_some());
+
+        let environ = identity.environ.unwrap();
+        assert_eq!(environ.get("PATH").map(|p| p.clone()), env::var("PATH").ok());
+    }
+
+    #[test]
+    fn test_proc_identity_fixture() {
+        let redact = DEFAULT_REDACT.iter().map(|r| String::from(*r)).collect::<Vec<String>>();
+        let identity = fixtures().process_identity(Pid::Id(1), Some(&redact[..])).unwrap();
+        assert_eq!(identity.command, "init");
+        assert_eq!(identity.argv, vec!["/usr/lib/systemd/systemd", "--switched-root",
+                                       "--system", "--deserialize", "31"]);
+        assert_eq!(identity.exe, Some(String::from("/usr/lib/systemd/systemd")));
+        assert_eq!(identity.cwd, Some(String::from("/")));
+        assert_eq!(identity.root, Some(String::from("/")));
+
+        let environ = identity.environ.unwrap();
+        assert_eq!(environ.len(), 5);
+        assert_eq!(environ["TERM"], "linux");
+        assert_eq!(environ["DB_PASSWORD"], REDACTED);
+        assert_eq!(environ["api_token"], REDACTED);
+
+        let identity = fixtures().process_identity(Pid::Id(1), None).unwrap();
+        assert_eq!(identity.environ, None);
+    }
+}
@@ -1,27 +1,27 @@
-use std::fs::File;
 use std::string::String;
-use std::io::{self, Read};
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProcStack {
-    kernel_trace: String,
+    pub kernel_trace: String,
 }
 
-pub fn process_stack(pid: String) -> io::Result<ProcStack> {
-    let mut f = try!(File::open(format!("/proc/{}/stack", pid)));
-    let mut trace = String::new();
-    let _n = f.read_to_string(&mut trace).unwrap();
+impl ProcFs {
+    pub fn process_stack(&self, pid: Pid) -> Result<ProcStack, ProcError> {
+        let trace = try!(read_proc_file(&self.path(&format!("{}/stack", pid))));
 
-    Ok(ProcStack {
-        kernel_trace: trace,
-    })
+        Ok(ProcStack {
+            kernel_trace: trace,
+        })
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::process::Command;
     use proc_fs::kernel::*;
-    use proc_fs::ToPid;
+    use proc_fs::{ProcFs, Pid, fixtures};
 
     #[test]
     fn test_proc_stack() {
@@ -31,10 +31,17 @@ mod test {
             .spawn()
             .unwrap_or_else(|e| { panic!("failed to execute process: {}", e) }).id();
 
-        let stats = process_stack(id.to_pid());
+        let proc_fs = ProcFs::default();
+        let stats = proc_fs.process_stack(Pid::Id(id));
         assert!(stats.is_ok());
 
-        let stats = process_stack("self".to_pid());
+        let stats = proc_fs.process_stack(Pid::Current);
         assert!(stats.is_ok());
     }
+
+    #[test]
+    fn test_proc_stack_fixture() {
+        let stack = fixtures().process_stack(Pid::Id(1)).unwrap();
+        assert!(stack.kernel_trace.starts_with("[<0>] ep_poll"));
+    }
 }
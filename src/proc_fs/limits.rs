@@ -0,0 +1,203 @@
+/*
+ * Resource limits of a process from /proc/<pid>/limits, and how close the
+ * process is to reaching them.
+ */
+use std::string::String;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field, page_size};
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Limit {
+    pub name: String, // e.g. "Max open files"
+    pub soft: Option<usize>, // None means unlimited
+    pub hard: Option<usize>, // None means unlimited
+    pub units: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Headroom {
+    pub name: String,
+    pub usage: usize,
+    pub soft: Option<usize>,
+    pub hard: Option<usize>,
+    pub used_percent: Option<f64>, // usage as a percentage of the soft limit
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LimitsReport {
+    pub limits: Vec<Limit>,
+    pub headroom: Vec<Headroom>,
+}
+
+pub const MAX_OPEN_FILES: &'static str = "Max open files";
+pub const MAX_RESIDENT_SET: &'static str = "Max resident set";
+pub const MAX_PROCESSES: &'static str = "Max processes";
+pub const MAX_LOCKED_MEMORY: &'static str = "Max locked memory";
+pub const MAX_ADDRESS_SPACE: &'static str = "Max address space";
+
+pub fn find_limit<'a>(limits: &'a [Limit], name: &str) -> Option<&'a Limit> {
+    limits.iter().find(|l| l.name == name)
+}
+
+fn parse_limit_value(path: &str, line: usize, name: &str, value: &str)
+    -> Result<Option<usize>, ProcError> {
+    match value {
+        "unlimited" => Ok(None),
+        _ => Ok(Some(try!(parse_field(path, line, name, value)))),
+    }
+}
+
+// The file is a fixed width table whose limit names contain spaces, so the
+// columns are located from the header:
+// Limit                     Soft Limit           Hard Limit           Units
+pub fn parse_limits(path: &str, limits: &str) -> Result<Vec<Limit>, ProcError> {
+    let mut lines = limits.lines();
+    let header = match lines.next() {
+        Some(header) => header,
+        None => return Err(ProcError::UnexpectedFormat(format!("{}: empty", path))),
+    };
+    let soft_col = header.find("Soft Limit");
+    let hard_col = header.find("Hard Limit");
+    let units_col = header.find("Units");
+    let (soft_col, hard_col, units_col) = match (soft_col, hard_col, units_col) {
+        (Some(s), Some(h), Some(u)) if s < h && h < u => (s, h, u),
+        _ => return Err(ProcError::UnexpectedFormat(format!("{}: unknown header", path))),
+    };
+
+    let column = |line: &str, start: usize, end: usize| -> String {
+        let end = if end > line.len() { line.len() } else { end };
+        if start >= end { String::new() } else { String::from(line[start..end].trim()) }
+    };
+
+    let mut parsed = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let n = i + 2;
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let name = column(line, 0, soft_col);
+        let soft = column(line, soft_col, hard_col);
+        let hard = column(line, hard_col, units_col);
+        let units = column(line, units_col, line.len());
+
+        parsed.push(Limit {
+            soft: try!(parse_limit_value(path, n, &name, &soft)),
+            hard: try!(parse_limit_value(path, n, &name, &hard)),
+            units: if units.is_empty() { None } else { Some(units) },
+            name: name,
+        });
+    }
+
+    Ok(parsed)
+}
+
+pub fn headroom(limits: &[Limit], name: &str, usage: usize) -> Headroom {
+    let (soft, hard) = match find_limit(limits, name) {
+        Some(limit) => (limit.soft, limit.hard),
+        None => (None, None),
+    };
+
+    Headroom {
+        name: String::from(name),
+        usage: usage,
+        soft: soft,
+        hard: hard,
+        used_percent: soft.map(|s| {
+            if s == 0 { 100.0 } else { 100.0 * usage as f64 / s as f64 }
+        }),
+    }
+}
+
+impl ProcFs {
+    pub fn process_limits(&self, pid: Pid) -> Result<Vec<Limit>, ProcError> {
+        let path = self.path(&format!("{}/limits", pid));
+        let limits = try!(read_proc_file(&path));
+
+        parse_limits(&path, &limits)
+    }
+
+    /// Current usage of the limits we can measure: open fds, RSS, threads,
+    /// locked memory and address space.
+    pub fn process_headroom(&self, pid: Pid) -> Result<Vec<Headroom>, ProcError> {
+        let limits = try!(self.process_limits(pid));
+        self.headroom_for(pid, &limits)
+    }
+
+    pub fn process_limits_report(&self, pid: Pid) -> Result<LimitsReport, ProcError> {
+        let limits = try!(self.process_limits(pid));
+        let headroom = try!(self.headroom_for(pid, &limits));
+
+        Ok(LimitsReport {
+            limits: limits,
+            headroom: headroom,
+        })
+    }
+
+    fn headroom_for(&self, pid: Pid, limits: &[Limit]) -> Result<Vec<Headroom>, ProcError> {
+        let fds = try!(self.process_fds(pid));
+        let statm = try!(self.process_statm(pid));
+        let stat = try!(self.process_stat(pid));
+        let status = try!(self.process_status(pid));
+
+        Ok(vec![
+            headroom(limits, MAX_OPEN_FILES, fds.len()),
+            headroom(limits, MAX_RESIDENT_SET, statm.resident * page_size()),
+            headroom(limits, MAX_PROCESSES, stat.num_threads as usize),
+            headroom(limits, MAX_LOCKED_MEMORY, status.vm_lck.unwrap_or(0)),
+            headroom(limits, MAX_ADDRESS_SPACE, stat.vsize),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::limits::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_proc_limits() {
+        let proc_fs = ProcFs::default();
+        let limits = proc_fs.process_limits(Pid::Current).unwrap();
+        assert!(find_limit(&limits, MAX_OPEN_FILES).is_some());
+
+        let report = proc_fs.process_limits_report(Pid::Current).unwrap();
+        assert_eq!(report.headroom.len(), 5);
+        assert!(report.headroom[0].usage > 0);
+    }
+
+    #[test]
+    fn test_proc_limits_fixture() {
+        let limits = fixtures().process_limits(Pid::Id(1)).unwrap();
+        assert_eq!(limits.len(), 16);
+
+        let cpu = find_limit(&limits, "Max cpu time").unwrap();
+        assert_eq!(cpu.soft, None);
+        assert_eq!(cpu.hard, None);
+        assert_eq!(cpu.units, Some(String::from("seconds")));
+
+        let files = find_limit(&limits, MAX_OPEN_FILES).unwrap();
+        assert_eq!(files.soft, Some(1024));
+        assert_eq!(files.hard, Some(524288));
+
+        let nice = find_limit(&limits, "Max nice priority").unwrap();
+        assert_eq!(nice.soft, Some(0));
+        assert_eq!(nice.units, None);
+    }
+
+    #[test]
+    fn test_headroom() {
+        let limits = fixtures().process_limits(Pid::Id(1)).unwrap();
+
+        let files = headroom(&limits, MAX_OPEN_FILES, 512);
+        assert_eq!(files.used_percent, Some(50.0));
+
+        let rss = headroom(&limits, MAX_RESIDENT_SET, 512);
+        assert_eq!(rss.soft, None);
+        assert_eq!(rss.used_percent, None);
+
+        let report = fixtures().process_limits_report(Pid::Id(1)).unwrap();
+        assert_eq!(report.headroom[0].usage, 7);
+        assert_eq!(report.headroom[2].usage, 1);
+    }
+}
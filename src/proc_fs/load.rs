@@ -0,0 +1,206 @@
+/*
+ * Load average from /proc/loadavg and pressure stall information from
+ * /proc/pressure/{cpu,memory,io}
+ */
+use proc_fs::{ProcFs, ProcError, read_proc_file, parse_field};
+
+// errno for reading pressure files when PSI was disabled at boot (psi=0).
+const EOPNOTSUPP: i32 = 95;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LoadAvg {
+    pub load1: f64,
+    pub load5: f64,
+    pub load15: f64,
+    pub running: usize, // runnable tasks
+    pub tasks: usize, // all tasks, threads included
+    pub last_pid: u32, // most recently created pid
+}
+
+// Share of wall time in which tasks were stalled on a resource, averaged
+// over 10, 60 and 300 seconds.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct PressureAverages {
+    pub avg10: f64, // percent
+    pub avg60: f64,
+    pub avg300: f64,
+    pub total: u64, // microseconds stalled since boot
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Pressure {
+    pub some: PressureAverages, // at least one task stalled
+    pub full: Option<PressureAverages>, // all non-idle tasks stalled, not for cpu before 5.13
+}
+
+// Each resource is None when the kernel has no PSI (before 4.20, or built
+// or booted without it).
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SystemPressure {
+    pub cpu: Option<Pressure>,
+    pub memory: Option<Pressure>,
+    pub io: Option<Pressure>,
+}
+
+impl SystemPressure {
+    pub fn is_available(&self) -> bool {
+        self.cpu.is_some() || self.memory.is_some() || self.io.is_some()
+    }
+}
+
+pub fn parse_loadavg(path: &str, loadavg: &str) -> Result<LoadAvg, ProcError> {
+    let fields = loadavg.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() < 5 {
+        return Err(ProcError::UnexpectedFormat(format!("{}: expected 5 fields", path)))
+    }
+
+    let mut tasks = fields[3].splitn(2, '/');
+    let (running, total) = match (tasks.next(), tasks.next()) {
+        (Some(running), Some(total)) => (running, total),
+        _ => return Err(ProcError::parse(path, 1, "tasks")),
+    };
+
+    Ok(LoadAvg {
+        load1: try!(parse_field(path, 1, "load1", fields[0])),
+        load5: try!(parse_field(path, 1, "load5", fields[1])),
+        load15: try!(parse_field(path, 1, "load15", fields[2])),
+        running: try!(parse_field(path, 1, "running", running)),
+        tasks: try!(parse_field(path, 1, "tasks", total)),
+        last_pid: try!(parse_field(path, 1, "last_pid", fields[4])),
+    })
+}
+
+fn parse_pressure_line(path: &str, line: usize, fields: &[&str])
+    -> Result<PressureAverages, ProcError> {
+    let mut averages = PressureAverages::default();
+
+    for field in fields {
+        let mut kv = field.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some("avg10"), Some(v)) => averages.avg10 = try!(parse_field(path, line, "avg10", v)),
+            (Some("avg60"), Some(v)) => averages.avg60 = try!(parse_field(path, line, "avg60", v)),
+            (Some("avg300"), Some(v)) => averages.avg300 = try!(parse_field(path, line, "avg300", v)),
+            (Some("total"), Some(v)) => averages.total = try!(parse_field(path, line, "total", v)),
+            _ => {},
+        }
+    }
+
+    Ok(averages)
+}
+
+pub fn parse_pressure(path: &str, pressure: &str) -> Result<Pressure, ProcError> {
+    let mut some = None;
+    let mut full = None;
+
+    for (i, line) in pressure.lines().enumerate() {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        match fields.first() {
+            Some(&"some") => some = Some(try!(parse_pressure_line(path, i + 1, &fields[1..]))),
+            Some(&"full") => full = Some(try!(parse_pressure_line(path, i + 1, &fields[1..]))),
+            _ => {},
+        }
+    }
+
+    match some {
+        Some(some) => Ok(Pressure { some: some, full: full }),
+        None => Err(ProcError::UnexpectedFormat(format!("{}: no some line", path))),
+    }
+}
+
+impl ProcFs {
+    pub fn loadavg(&self) -> Result<LoadAvg, ProcError> {
+        let path = self.path("loadavg");
+        let loadavg = try!(read_proc_file(&path));
+
+        parse_loadavg(&path, &loadavg)
+    }
+
+    /// Pressure of a single resource: "cpu", "memory" or "io". `None` if the
+    /// kernel does not provide it.
+    pub fn pressure(&self, resource: &str) -> Result<Option<Pressure>, ProcError> {
+        let path = self.path(&format!("pressure/{}", resource));
+        let pressure = match read_proc_file(&path) {
+            Ok(pressure) => pressure,
+            Err(ProcError::NotFound(_)) => return Ok(None),
+            Err(ProcError::Io(ref e)) if e.raw_os_error() == Some(EOPNOTSUPP) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        parse_pressure(&path, &pressure).map(Some)
+    }
+
+    pub fn system_pressure(&self) -> Result<SystemPressure, ProcError> {
+        Ok(SystemPressure {
+            cpu: try!(self.pressure("cpu")),
+            memory: try!(self.pressure("memory")),
+            io: try!(self.pressure("io")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::load::*;
+    use proc_fs::{ProcFs, fixtures};
+
+    #[test]
+    fn test_loadavg() {
+        let loadavg = ProcFs::default().loadavg().unwrap();
+        assert!(loadavg.running >= 1);
+        assert!(loadavg.tasks >= loadavg.running);
+    }
+
+    #[test]
+    fn test_loadavg_fixture() {
+        let loadavg = fixtures().loadavg().unwrap();
+        assert_eq!(loadavg.load1, 0.52);
+        assert_eq!(loadavg.load5, 0.58);
+        assert_eq!(loadavg.load15, 0.59);
+        assert_eq!(loadavg.running, 3);
+        assert_eq!(loadavg.tasks, 712);
+        assert_eq!(loadavg.last_pid, 31337);
+
+        assert!(parse_loadavg("loadavg", "0.52 0.58 0.59 3 31337\n").is_err());
+        assert!(parse_loadavg("loadavg", "0.52 0.58\n").is_err());
+    }
+
+    #[test]
+    fn test_system_pressure_fixture() {
+        let pressure = fixtures().system_pressure().unwrap();
+        assert!(pressure.is_available());
+
+        let cpu = pressure.cpu.unwrap();
+        assert_eq!(cpu.some.avg10, 1.53);
+        assert_eq!(cpu.some.avg300, 0.41);
+        assert_eq!(cpu.some.total, 128004512);
+
+        let io = pressure.io.unwrap();
+        assert_eq!(io.some.avg60, 8.01);
+        assert_eq!(io.full, Some(PressureAverages {
+            avg10: 9.75,
+            avg60: 6.20,
+            avg300: 2.58,
+            total: 711264003,
+        }));
+        assert_eq!(pressure.memory.unwrap().full.unwrap().total, 1930020);
+    }
+
+    #[test]
+    fn test_pressure_absent() {
+        let pressure = ProcFs::new("/nonexistent/proc").system_pressure().unwrap();
+        assert!(!pressure.is_available());
+        assert_eq!(pressure.cpu, None);
+    }
+
+    #[test]
+    fn test_parse_pressure() {
+        // cpu has no full line before 5.13
+        let pressure = parse_pressure("cpu", "some avg10=0.10 avg60=0.20 avg300=0.30 total=42\n").unwrap();
+        assert_eq!(pressure.some.avg60, 0.20);
+        assert_eq!(pressure.some.total, 42);
+        assert_eq!(pressure.full, None);
+
+        assert!(parse_pressure("cpu", "").is_err());
+        assert!(parse_pressure("cpu", "some avg10=x avg60=0.20 avg300=0.30 total=42\n").is_err());
+    }
+}
@@ -0,0 +1,278 @@
+/*
+ * Host memory statistics from /proc/meminfo and /proc/vmstat
+ */
+use time::precise_time_ns;
+
+use proc_fs::{ProcFs, ProcError, read_proc_file, parse_field};
+
+// Sizes are converted from kB to bytes. Keys missing on older kernels read
+// as 0, except MemAvailable (3.14+) whose absence callers need to know about.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct MemInfo {
+    pub mem_total: usize,
+    pub mem_free: usize,
+    pub mem_available: Option<usize>, // estimate of memory available without swapping
+    pub buffers: usize,
+    pub cached: usize, // page cache, excluding swap cache
+    pub swap_cached: usize,
+    pub swap_total: usize,
+    pub swap_free: usize,
+    pub dirty: usize, // waiting to be written back to disk
+    pub writeback: usize, // being written back to disk
+    pub shmem: usize,
+    pub slab: usize, // kernel data structures, SReclaimable + SUnreclaim
+    pub slab_reclaimable: usize,
+    pub slab_unreclaimable: usize,
+    pub huge_pages_total: usize, // a count of pages, not bytes
+    pub huge_pages_free: usize,
+    pub huge_pages_reserved: usize,
+    pub huge_pages_surplus: usize,
+    pub huge_page_size: usize,
+}
+
+// Counters since boot. oom_kill is only there since 4.13.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct VmStat {
+    pub pgfault: usize, // page faults, minor and major
+    pub pgmajfault: usize, // page faults that needed I/O
+    pub pswpin: usize, // pages swapped in
+    pub pswpout: usize, // pages swapped out
+    pub oom_kill: usize,
+    pub compact_stall: usize, // direct compaction attempts
+    pub compact_fail: usize,
+    pub compact_success: usize,
+}
+
+// The VmStat counters as events per second between two samples.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct VmStatRates {
+    pub pgfault: f64,
+    pub pgmajfault: f64,
+    pub pswpin: f64,
+    pub pswpout: f64,
+    pub oom_kill: f64,
+    pub compact_stall: f64,
+    pub compact_fail: f64,
+    pub compact_success: f64,
+    pub window_secs: f64,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SystemMemory {
+    pub meminfo: MemInfo,
+    pub vmstat: VmStat,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SystemMemoryRates {
+    pub meminfo: MemInfo,
+    pub rates: Option<VmStatRates>, // None on the first sample
+}
+
+pub fn parse_meminfo(path: &str, meminfo: &str) -> Result<MemInfo, ProcError> {
+    let mut info = MemInfo::default();
+    let mut has_total = false;
+
+    for (i, line) in meminfo.lines().enumerate() {
+        let n = i + 1;
+        let mut kv = line.splitn(2, ':');
+        let (key, value) = match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) => (k, v.trim()),
+            _ => continue,
+        };
+
+        // HugePages_* are counts, every other value is in kB.
+        let is_kb = value.ends_with("kB");
+        let value: usize = try!(parse_field(path, n, key, value.trim_right_matches("kB").trim()));
+        let value = if is_kb { value * 1024 } else { value };
+
+        match key {
+            "MemTotal" => {
+                info.mem_total = value;
+                has_total = true;
+            },
+            "MemFree" => info.mem_free = value,
+            "MemAvailable" => info.mem_available = Some(value),
+            "Buffers" => info.buffers = value,
+            "Cached" => info.cached = value,
+            "SwapCached" => info.swap_cached = value,
+            "SwapTotal" => info.swap_total = value,
+            "SwapFree" => info.swap_free = value,
+            "Dirty" => info.dirty = value,
+            "Writeback" => info.writeback = value,
+            "Shmem" => info.shmem = value,
+            "Slab" => info.slab = value,
+            "SReclaimable" => info.slab_reclaimable = value,
+            "SUnreclaim" => info.slab_unreclaimable = value,
+            "HugePages_Total" => info.huge_pages_total = value,
+            "HugePages_Free" => info.huge_pages_free = value,
+            "HugePages_Rsvd" => info.huge_pages_reserved = value,
+            "HugePages_Surp" => info.huge_pages_surplus = value,
+            "Hugepagesize" => info.huge_page_size = value,
+            _ => {},
+        }
+    }
+
+    if !has_total {
+        return Err(ProcError::UnexpectedFormat(format!("{}: no MemTotal", path)))
+    }
+    Ok(info)
+}
+
+pub fn parse_vmstat(path: &str, vmstat: &str) -> Result<VmStat, ProcError> {
+    let mut stat = VmStat::default();
+
+    for (i, line) in vmstat.lines().enumerate() {
+        let mut kv = line.split_whitespace();
+        let (key, value) = match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) => (k, v),
+            _ => continue,
+        };
+
+        let field = match key {
+            "pgfault" => &mut stat.pgfault,
+            "pgmajfault" => &mut stat.pgmajfault,
+            "pswpin" => &mut stat.pswpin,
+            "pswpout" => &mut stat.pswpout,
+            "oom_kill" => &mut stat.oom_kill,
+            "compact_stall" => &mut stat.compact_stall,
+            "compact_fail" => &mut stat.compact_fail,
+            "compact_success" => &mut stat.compact_success,
+            _ => continue,
+        };
+        *field = try!(parse_field(path, i + 1, key, value));
+    }
+
+    Ok(stat)
+}
+
+pub fn vmstat_rates(prev: &VmStat, cur: &VmStat, window_secs: f64) -> VmStatRates {
+    let rate = |prev: usize, cur: usize| {
+        if window_secs <= 0.0 { 0.0 } else { cur.saturating_sub(prev) as f64 / window_secs }
+    };
+
+    VmStatRates {
+        pgfault: rate(prev.pgfault, cur.pgfault),
+        pgmajfault: rate(prev.pgmajfault, cur.pgmajfault),
+        pswpin: rate(prev.pswpin, cur.pswpin),
+        pswpout: rate(prev.pswpout, cur.pswpout),
+        oom_kill: rate(prev.oom_kill, cur.oom_kill),
+        compact_stall: rate(prev.compact_stall, cur.compact_stall),
+        compact_fail: rate(prev.compact_fail, cur.compact_fail),
+        compact_success: rate(prev.compact_success, cur.compact_success),
+        window_secs: window_secs,
+    }
+}
+
+impl ProcFs {
+    pub fn meminfo(&self) -> Result<MemInfo, ProcError> {
+        let path = self.path("meminfo");
+        let meminfo = try!(read_proc_file(&path));
+
+        parse_meminfo(&path, &meminfo)
+    }
+
+    pub fn vmstat(&self) -> Result<VmStat, ProcError> {
+        let path = self.path("vmstat");
+        let vmstat = try!(read_proc_file(&path));
+
+        parse_vmstat(&path, &vmstat)
+    }
+
+    pub fn system_memory(&self) -> Result<SystemMemory, ProcError> {
+        Ok(SystemMemory {
+            meminfo: try!(self.meminfo()),
+            vmstat: try!(self.vmstat()),
+        })
+    }
+}
+
+/// Keeps the previous vmstat sample around so that a periodic caller gets
+/// counter rates since its last call.
+pub struct SystemMemorySampler {
+    proc_fs: ProcFs,
+    last: Option<(VmStat, u64)>, // with the precise_time_ns it was taken at
+}
+
+impl SystemMemorySampler {
+    pub fn new(proc_fs: ProcFs) -> SystemMemorySampler {
+        SystemMemorySampler {
+            proc_fs: proc_fs,
+            last: None,
+        }
+    }
+
+    pub fn sample(&mut self) -> Result<SystemMemoryRates, ProcError> {
+        let memory = try!(self.proc_fs.system_memory());
+        let now = precise_time_ns();
+        let rates = self.last.as_ref().map(|&(ref prev, then)| {
+            vmstat_rates(prev, &memory.vmstat, (now - then) as f64 / 1e9)
+        });
+        self.last = Some((memory.vmstat, now));
+
+        Ok(SystemMemoryRates {
+            meminfo: memory.meminfo,
+            rates: rates,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::meminfo::*;
+    use proc_fs::{ProcFs, fixtures};
+
+    #[test]
+    fn test_system_memory() {
+        let memory = ProcFs::default().system_memory().unwrap();
+        assert!(memory.meminfo.mem_total > 0);
+        assert!(memory.meminfo.mem_free <= memory.meminfo.mem_total);
+        assert!(memory.vmstat.pgfault > 0);
+    }
+
+    #[test]
+    fn test_system_memory_fixture() {
+        let memory = fixtures().system_memory().unwrap();
+        let meminfo = memory.meminfo;
+        assert_eq!(meminfo.mem_total, 16303428 * 1024);
+        assert_eq!(meminfo.mem_available, Some(9876540 * 1024));
+        assert_eq!(meminfo.swap_free, 2001234 * 1024);
+        assert_eq!(meminfo.slab_unreclaimable, 200000 * 1024);
+        assert_eq!(meminfo.huge_pages_total, 16);
+        assert_eq!(meminfo.huge_pages_reserved, 2);
+        assert_eq!(meminfo.huge_page_size, 2048 * 1024);
+
+        let vmstat = memory.vmstat;
+        assert_eq!(vmstat.pgfault, 912345678);
+        assert_eq!(vmstat.pgmajfault, 23456);
+        assert_eq!(vmstat.pswpout, 5012);
+        assert_eq!(vmstat.oom_kill, 2);
+        assert_eq!(vmstat.compact_success, 90);
+    }
+
+    #[test]
+    fn test_parse_meminfo_without_available() {
+        let meminfo = parse_meminfo("meminfo", "MemTotal: 1024 kB\nMemFree: 512 kB\n").unwrap();
+        assert_eq!(meminfo.mem_available, None);
+        assert!(parse_meminfo("meminfo", "MemFree: 512 kB\n").is_err());
+    }
+
+    #[test]
+    fn test_vmstat_rates() {
+        let prev = VmStat { pgfault: 1000, oom_kill: 1, ..VmStat::default() };
+        let cur = VmStat { pgfault: 3000, oom_kill: 2, ..VmStat::default() };
+
+        let rates = vmstat_rates(&prev, &cur, 4.0);
+        assert_eq!(rates.pgfault, 500.0);
+        assert_eq!(rates.oom_kill, 0.25);
+        assert_eq!(rates.pswpin, 0.0);
+        assert_eq!(vmstat_rates(&prev, &cur, 0.0).pgfault, 0.0);
+    }
+
+    #[test]
+    fn test_system_memory_sampler() {
+        let mut sampler = SystemMemorySampler::new(ProcFs::default());
+        assert!(sampler.sample().unwrap().rates.is_none());
+        assert!(sampler.sample().unwrap().rates.is_some());
+    }
+}
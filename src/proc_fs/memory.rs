@@ -0,0 +1,275 @@
+/*
+ * Memory mapping breakdown of a process from /proc/<pid>/smaps and
+ * /proc/<pid>/smaps_rollup
+ */
+use std::collections::BTreeMap;
+use std::string::String;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field};
+
+// All sizes are in bytes.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    pub size: usize, // virtual size, not present in smaps_rollup
+    pub rss: usize, // resident set size
+    pub pss: usize, // proportional set size, shared pages divided among sharers
+    pub uss: usize, // unique set size, Private_Clean + Private_Dirty
+    pub shared_clean: usize,
+    pub shared_dirty: usize,
+    pub private_clean: usize,
+    pub private_dirty: usize,
+    pub anonymous: usize, // resident pages not backed by a file
+    pub swap: usize,
+    pub swap_pss: usize, // proportional share of swap
+    pub locked: usize,
+}
+
+impl MemoryUsage {
+    fn add(&mut self, other: &MemoryUsage) {
+        self.size += other.size;
+        self.rss += other.rss;
+        self.pss += other.pss;
+        self.uss += other.uss;
+        self.shared_clean += other.shared_clean;
+        self.shared_dirty += other.shared_dirty;
+        self.private_clean += other.private_clean;
+        self.private_dirty += other.private_dirty;
+        self.anonymous += other.anonymous;
+        self.swap += other.swap;
+        self.swap_pss += other.swap_pss;
+        self.locked += other.locked;
+    }
+
+    // Keys that are not memory counters, e.g. VmFlags, are ignored.
+    fn set(&mut self, path: &str, line: usize, key: &str, value: &str) -> Result<(), ProcError> {
+        let field = match key {
+            "Size" => &mut self.size,
+            "Rss" => &mut self.rss,
+            "Pss" => &mut self.pss,
+            "Shared_Clean" => &mut self.shared_clean,
+            "Shared_Dirty" => &mut self.shared_dirty,
+            "Private_Clean" => &mut self.private_clean,
+            "Private_Dirty" => &mut self.private_dirty,
+            "Anonymous" => &mut self.anonymous,
+            "Swap" => &mut self.swap,
+            "SwapPss" => &mut self.swap_pss,
+            "Locked" => &mut self.locked,
+            _ => return Ok(()),
+        };
+        let kb: usize = try!(parse_field(path, line, key, value.trim_right_matches("kB").trim()));
+        *field = kb * 1024;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Mapping {
+    pub start: usize,
+    pub end: usize,
+    pub perms: String, // e.g. "r-xp"
+    pub offset: usize,
+    pub pathname: Option<String>, // backing file, or [heap], [stack], etc.
+    pub usage: MemoryUsage,
+}
+
+/// All mappings of a single backing file.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FileMappings {
+    pub pathname: String,
+    pub mappings: Vec<Mapping>,
+    pub total: MemoryUsage,
+}
+
+/// Totals for a library, regardless of which directory it was loaded from.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LibraryUsage {
+    pub library: String,
+    pub mappings: usize,
+    pub total: MemoryUsage,
+}
+
+const ANONYMOUS: &'static str = "[anon]";
+
+impl ProcFs {
+    /// Memory totals for the whole process. Uses smaps_rollup where the
+    /// kernel has it (4.14+) and sums the full smaps otherwise.
+    pub fn process_smaps_rollup(&self, pid: Pid) -> Result<MemoryUsage, ProcError> {
+        let path = self.path(&format!("{}/smaps_rollup", pid));
+        match read_proc_file(&path) {
+            Ok(rollup) => {
+                let mut mappings = try!(parse_smaps(&path, &rollup));
+                match mappings.pop() {
+                    Some(mapping) => Ok(mapping.usage),
+                    None => Err(ProcError::UnexpectedFormat(format!("{}: empty rollup", path))),
+                }
+            },
+            Err(ProcError::NotFound(_)) => {
+                let mappings = try!(self.process_smaps(pid));
+                let mut total = MemoryUsage::default();
+                for mapping in mappings.iter() {
+                    total.add(&mapping.usage);
+                }
+                Ok(total)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn process_smaps(&self, pid: Pid) -> Result<Vec<Mapping>, ProcError> {
+        let path = self.path(&format!("{}/smaps", pid));
+        let smaps = try!(read_proc_file(&path));
+
+        parse_smaps(&path, &smaps)
+    }
+}
+
+fn parse_hex(path: &str, line: usize, field: &str, value: &str) -> Result<usize, ProcError> {
+    usize::from_str_radix(value, 16).map_err(|_| ProcError::parse(path, line, field))
+}
+
+// A mapping header looks like
+// 7f4c0a1e3000-7f4c0a208000 r--p 00000000 fe:00 1835019   /usr/lib/libc.so.6
+fn parse_mapping_header(path: &str, line: usize, header: &str) -> Result<Mapping, ProcError> {
+    let fields = header.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() < 5 {
+        return Err(ProcError::parse(path, line, "mapping"))
+    }
+
+    let mut range = fields[0].splitn(2, '-');
+    let (start, end) = match (range.next(), range.next()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Err(ProcError::parse(path, line, "address")),
+    };
+    let pathname = if fields.len() > 5 { Some(fields[5..].join(" ")) } else { None };
+
+    Ok(Mapping {
+        start: try!(parse_hex(path, line, "start", start)),
+        end: try!(parse_hex(path, line, "end", end)),
+        perms: String::from(fields[1]),
+        offset: try!(parse_hex(path, line, "offset", fields[2])),
+        pathname: pathname,
+        usage: MemoryUsage::default(),
+    })
+}
+
+pub fn parse_smaps(path: &str, smaps: &str) -> Result<Vec<Mapping>, ProcError> {
+    let mut mappings: Vec<Mapping> = Vec::new();
+
+    for (i, line) in smaps.lines().enumerate() {
+        let n = i + 1;
+        let mut kv = line.splitn(2, ':');
+        let (key, value) = match (kv.next(), kv.next()) {
+            // Header lines also contain a ':' in the device column, but the
+            // key part then contains whitespace.
+            (Some(k), Some(v)) if !k.contains(' ') => (k, v.trim()),
+            _ => {
+                mappings.push(try!(parse_mapping_header(path, n, line)));
+                continue
+            },
+        };
+
+        match mappings.last_mut() {
+            Some(mapping) => try!(mapping.usage.set(path, n, key, value)),
+            None => return Err(ProcError::parse(path, n, key)),
+        }
+    }
+
+    for mapping in mappings.iter_mut() {
+        mapping.usage.uss = mapping.usage.private_clean + mapping.usage.private_dirty;
+    }
+
+    Ok(mappings)
+}
+
+/// Groups mappings by their backing file. Mappings without one are grouped
+/// under "[anon]".
+pub fn group_by_file(mappings: Vec<Mapping>) -> Vec<FileMappings> {
+    let mut groups: BTreeMap<String, FileMappings> = BTreeMap::new();
+
+    for mapping in mappings.into_iter() {
+        let pathname = mapping.pathname.clone().unwrap_or(String::from(ANONYMOUS));
+        let group = groups.entry(pathname.clone()).or_insert(FileMappings {
+            pathname: pathname,
+            mappings: Vec::new(),
+            total: MemoryUsage::default(),
+        });
+        group.total.add(&mapping.usage);
+        group.mappings.push(mapping);
+    }
+
+    groups.into_iter().map(|(_k, v)| v).collect()
+}
+
+/// Sums mappings by library file name, so the same library loaded from
+/// different directories (e.g. inside and outside a chroot) is one entry.
+pub fn group_by_library(mappings: Vec<Mapping>) -> Vec<LibraryUsage> {
+    let mut groups: BTreeMap<String, LibraryUsage> = BTreeMap::new();
+
+    for mapping in mappings.iter() {
+        let library = match mapping.pathname {
+            Some(ref p) => String::from(p.rsplit('/').next().unwrap_or(&p[..])),
+            None => String::from(ANONYMOUS),
+        };
+        let group = groups.entry(library.clone()).or_insert(LibraryUsage {
+            library: library,
+            mappings: 0,
+            total: MemoryUsage::default(),
+        });
+        group.mappings += 1;
+        group.total.add(&mapping.usage);
+    }
+
+    groups.into_iter().map(|(_k, v)| v).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::memory::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_proc_smaps() {
+        let proc_fs = ProcFs::default();
+        let mappings = proc_fs.process_smaps(Pid::Current).unwrap();
+        assert!(mappings.len() > 0);
+
+        let rollup = proc_fs.process_smaps_rollup(Pid::Current).unwrap();
+        assert!(rollup.rss > 0);
+    }
+
+    #[test]
+    fn test_proc_smaps_fixture() {
+        let proc_fs = fixtures();
+        let mappings = proc_fs.process_smaps(Pid::Id(1)).unwrap();
+        assert_eq!(mappings.len(), 4);
+        assert_eq!(mappings[0].start, 0x55d2f2d6b000);
+        assert_eq!(mappings[0].perms, "r--p");
+        assert_eq!(mappings[0].pathname, Some(String::from("/usr/lib/systemd/systemd")));
+        assert_eq!(mappings[2].pathname, Some(String::from("[heap]")));
+        assert_eq!(mappings[3].pathname, None);
+        assert_eq!(mappings[1].usage.private_dirty, 8 * 1024);
+        assert_eq!(mappings[1].usage.uss, 12 * 1024);
+
+        let rollup = proc_fs.process_smaps_rollup(Pid::Id(1)).unwrap();
+        assert_eq!(rollup.rss, 10612 * 1024);
+        assert_eq!(rollup.pss, 4213 * 1024);
+        assert_eq!(rollup.uss, (1204 + 2760) * 1024);
+    }
+
+    #[test]
+    fn test_group_mappings() {
+        let mappings = fixtures().process_smaps(Pid::Id(1)).unwrap();
+
+        let files = group_by_file(mappings.clone());
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].pathname, "/usr/lib/systemd/systemd");
+        assert_eq!(files[0].mappings.len(), 2);
+        assert_eq!(files[0].total.rss, (132 + 20) * 1024);
+        assert_eq!(files[1].pathname, "[anon]");
+
+        let libraries = group_by_library(mappings);
+        assert_eq!(libraries.len(), 3);
+        assert_eq!(libraries[2].library, "systemd");
+        assert_eq!(libraries[2].mappings, 2);
+    }
+}
@@ -1,31 +1,223 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use libc;
+
 pub mod stats;
 pub mod kernel;
 pub mod net;
+pub mod cpu;
+pub mod pid;
+pub mod status;
+pub mod memory;
+pub mod fds;
+pub mod threads;
+pub mod limits;
+pub mod identity;
+pub mod processes;
+pub mod tree;
+pub mod system;
+pub mod meminfo;
+pub mod load;
+pub mod netdev;
+pub mod sockets;
+pub mod sockstat;
+pub mod diskstats;
+pub mod filesystems;
+
+pub use self::pid::Pid;
 
-pub trait ToPid {
-    fn to_pid(&self) -> String;
+/// Handle to a mounted proc filesystem. Every collector is a method on it, so
+/// it can be pointed at a host's proc mounted inside a container or at a
+/// directory of captured fixture files.
+#[derive(Clone, Debug)]
+pub struct ProcFs {
+    root: PathBuf,
 }
 
-impl ToPid for usize {
-    fn to_pid(&self) -> String {
-        format!("{}", self)
+impl ProcFs {
+    pub fn new<P: AsRef<Path>>(root: P) -> ProcFs {
+        ProcFs {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of a file relative to the proc root, e.g. `path("net/snmp")`.
+    pub fn path(&self, relative: &str) -> String {
+        self.root.join(relative).to_string_lossy().into_owned()
+    }
+
+    /// Whether the process currently has a directory under the proc root.
+    pub fn exists(&self, pid: Pid) -> bool {
+        self.root.join(format!("{}", pid)).is_dir()
     }
 }
 
-impl ToPid for u32 {
-    fn to_pid(&self) -> String {
-        format!("{}", self)
+impl Default for ProcFs {
+    fn default() -> ProcFs {
+        ProcFs::new("/proc")
     }
 }
 
-impl ToPid for String {
-    fn to_pid(&self) -> String {
-        self.clone()
+// errno returned when reading files of a process that has already exited.
+const ESRCH: i32 = 3;
+
+#[derive(Debug)]
+pub enum ProcError {
+    NotFound(String),
+    PermissionDenied(String),
+    Parse { file: String, line: usize, field: String },
+    UnexpectedFormat(String),
+    Io(io::Error),
+}
+
+impl ProcError {
+    pub fn parse(file: &str, line: usize, field: &str) -> ProcError {
+        ProcError::Parse {
+            file: String::from(file),
+            line: line,
+            field: String::from(field),
+        }
+    }
+
+    fn from_io(path: &str, err: io::Error) -> ProcError {
+        if err.raw_os_error() == Some(ESRCH) {
+            return ProcError::NotFound(String::from(path))
+        }
+
+        match err.kind() {
+            io::ErrorKind::NotFound => ProcError::NotFound(String::from(path)),
+            io::ErrorKind::PermissionDenied => ProcError::PermissionDenied(String::from(path)),
+            _ => ProcError::Io(err),
+        }
+    }
+}
+
+impl From<io::Error> for ProcError {
+    fn from(err: io::Error) -> ProcError {
+        ProcError::Io(err)
+    }
+}
+
+impl Error for ProcError {
+    fn description(&self) -> &str {
+        match *self {
+            ProcError::NotFound(_) => "proc file not found",
+            ProcError::PermissionDenied(_) => "permission denied reading proc file",
+            ProcError::Parse { .. } => "could not parse proc file",
+            ProcError::UnexpectedFormat(_) => "unexpected proc file format",
+            ProcError::Io(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ProcError::Io(ref err) => Some(err),
+            _ => None,
+        }
     }
 }
 
-impl<'a> ToPid for &'a str {
-    fn to_pid(&self) -> String {
-        self.to_string()
+impl fmt::Display for ProcError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProcError::NotFound(ref path) =>
+                write!(fmt, "NotFound: {}", path),
+            ProcError::PermissionDenied(ref path) =>
+                write!(fmt, "PermissionDenied: {}", path),
+            ProcError::Parse { ref file, line, ref field } =>
+                write!(fmt, "Parse: {} line {} field {}", file, line, field),
+            ProcError::UnexpectedFormat(ref msg) =>
+                write!(fmt, "UnexpectedFormat: {}", msg),
+            ProcError::Io(ref err) =>
+                write!(fmt, "Io: {}", err),
+        }
+    }
+}
+
+/// Reads a whole proc file, mapping a missing file or an exited process to
+/// `ProcError::NotFound`.
+pub fn read_proc_file(path: &str) -> Result<String, ProcError> {
+    let mut f = try!(File::open(path).map_err(|e| ProcError::from_io(path, e)));
+    let mut contents = String::new();
+    try!(f.read_to_string(&mut contents).map_err(|e| ProcError::from_io(path, e)));
+    Ok(contents)
+}
+
+/// Reads a proc file that may not be valid UTF-8, e.g. cmdline or environ.
+pub fn read_proc_bytes(path: &str) -> Result<Vec<u8>, ProcError> {
+    let mut f = try!(File::open(path).map_err(|e| ProcError::from_io(path, e)));
+    let mut contents = Vec::new();
+    try!(f.read_to_end(&mut contents).map_err(|e| ProcError::from_io(path, e)));
+    Ok(contents)
+}
+
+/// Parses a single value, reporting where it came from on failure.
+pub fn parse_field<T: FromStr>(file: &str, line: usize, field: &str, value: &str)
+    -> Result<T, ProcError> {
+    T::from_str(value).map_err(|_| ProcError::parse(file, line, field))
+}
+
+/// Size of a memory page in bytes, the unit of statm and stat's rss.
+pub fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Captured proc files under tests/fixtures/proc.
+#[cfg(test)]
+pub fn fixtures() -> ProcFs {
+    ProcFs::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/proc"))
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::*;
+
+    #[test]
+    fn test_proc_fs_exists() {
+        let proc_fs = ProcFs::default();
+        assert!(proc_fs.exists(Pid::Current));
+        assert!(!proc_fs.exists(Pid::Id(::std::u32::MAX)));
+
+        assert!(fixtures().exists(Pid::Id(1)));
+        assert!(fixtures().exists(Pid::Id(412)));
+        assert!(!fixtures().exists(Pid::Id(2)));
+    }
+
+    #[test]
+    fn test_proc_fs_path() {
+        assert_eq!(ProcFs::default().path("net/snmp"), "/proc/net/snmp");
+        assert_eq!(ProcFs::new("/host/proc").path("1/statm"), "/host/proc/1/statm");
+    }
+
+    #[test]
+    fn test_read_proc_file_not_found() {
+        match read_proc_file("/proc/0/statm") {
+            Err(ProcError::NotFound(path)) => assert_eq!(path, "/proc/0/statm"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_field() {
+        let n: usize = parse_field("/proc/self/statm", 1, "size", "42").unwrap();
+        assert_eq!(n, 42);
+
+        match parse_field::<usize>("/proc/self/statm", 1, "size", "x") {
+            Err(ProcError::Parse { file, line, field }) => {
+                assert_eq!(file, "/proc/self/statm");
+                assert_eq!(line, 1);
+                assert_eq!(field, "size");
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 }
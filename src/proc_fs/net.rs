@@ -1,7 +1,9 @@
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::fs;
 use std::string::String;
-use std::str::FromStr;
-use std::io::{self, Read};
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field};
+use proc_fs::netdev::NetDevice;
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct TcpStat {
@@ -21,36 +23,260 @@ pub struct TcpStat {
     resets_sent: usize,
 }
 
-pub fn process_tcp<'a>() -> io::Result<TcpStat> {
-    let mut f = try!(File::open("/proc/net/snmp"));
-    let mut snmp = String::new();
-    let _n = f.read_to_string(&mut snmp).unwrap();
+// Every section of /proc/net/snmp, /proc/net/netstat and /proc/net/snmp6,
+// e.g. "Tcp", "TcpExt" or "Udp6", keyed by counter name.
+pub type SnmpSection = BTreeMap<String, i64>;
+pub type Snmp = BTreeMap<String, SnmpSection>;
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct UdpStat {
+    pub in_datagrams: usize,
+    pub no_ports: usize, // datagrams to a port nobody listens on
+    pub in_errors: usize, // includes rcvbuf_errors
+    pub out_datagrams: usize,
+    pub rcvbuf_errors: usize, // dropped because a socket buffer was full
+    pub sndbuf_errors: usize,
+    pub in_csum_errors: usize,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TcpExtStat {
+    pub listen_overflows: usize, // accept queue full
+    pub listen_drops: usize, // SYNs dropped on a listening socket, overflows included
+    pub tcp_timeouts: usize, // retransmission timer expiries
+    pub tcp_syn_retrans: usize,
+    pub tcp_backlog_drop: usize,
+    pub tcp_abort_on_memory: usize,
+    pub tcp_abort_on_timeout: usize,
+}
+
+// Counters of the network namespace a process is in.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct NetworkStat {
+    pub net_ns: Option<u64>, // namespace inode, None if we may not look at the process
+    pub tcp: TcpStat,
+    pub interfaces: Vec<NetDevice>,
+}
+
+impl ProcFs {
+    /// TCP counters of our own network namespace.
+    pub fn process_tcp(&self) -> Result<TcpStat, ProcError> {
+        let path = self.path("net/snmp");
+        let snmp = try!(read_proc_file(&path));
+        parse_tcp(&path, &snmp)
+    }
+
+    /// Inode of the network namespace of the process, from the
+    /// "net:[4026531992]" link in /proc/<pid>/ns/net. Processes with the
+    /// same inode share their network stack.
+    pub fn process_net_namespace(&self, pid: Pid) -> Result<u64, ProcError> {
+        let path = self.path(&format!("{}/ns/net", pid));
+        let link = try!(fs::read_link(&path).map_err(|e| ProcError::from_io(&path, e)));
+        let link = link.to_string_lossy();
+        parse_field(&path, 1, "net", link.trim_left_matches("net:[").trim_right_matches(']'))
+    }
+
+    /// TCP counters of the network namespace the process is in, which need
+    /// not be ours, e.g. for a process in a container.
+    pub fn process_net_tcp(&self, pid: Pid) -> Result<TcpStat, ProcError> {
+        let path = self.path(&format!("{}/net/snmp", pid));
+        let snmp = try!(read_proc_file(&path));
+        parse_tcp(&path, &snmp)
+    }
+
+    pub fn process_network(&self, pid: Pid) -> Result<NetworkStat, ProcError> {
+        let net_ns = match self.process_net_namespace(pid) {
+            Ok(net_ns) => Some(net_ns),
+            Err(ProcError::PermissionDenied(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(NetworkStat {
+            net_ns: net_ns,
+            tcp: try!(self.process_net_tcp(pid)),
+            interfaces: try!(self.process_net_dev(pid)),
+        })
+    }
+
+    // snmp and netstat are always there, snmp6 only with IPv6 enabled.
+    fn read_snmp(&self, net_dir: &str) -> Result<Snmp, ProcError> {
+        let mut snmp = Snmp::new();
+        for file in &["snmp", "netstat"] {
+            let path = self.path(&format!("{}/{}", net_dir, file));
+            let contents = try!(read_proc_file(&path));
+            snmp.extend(try!(parse_snmp(&path, &contents)));
+        }
+
+        let path = self.path(&format!("{}/snmp6", net_dir));
+        match read_proc_file(&path) {
+            Ok(contents) => snmp.extend(try!(parse_snmp6(&path, &contents))),
+            Err(ProcError::NotFound(_)) => {},
+            Err(e) => return Err(e),
+        }
+        Ok(snmp)
+    }
 
+    pub fn net_snmp(&self) -> Result<Snmp, ProcError> {
+        self.read_snmp("net")
+    }
 
-    let mut tcp = snmp.lines()
-        .filter(|s| &s[0..3] == "Tcp")
-        .map(|s| &s[5..])
-        .collect::<Vec<&str>>().into_iter();
+    /// A single section, e.g. "TcpExt". `ProcError::NotFound` if no file has it.
+    pub fn net_snmp_section(&self, name: &str) -> Result<SnmpSection, ProcError> {
+        let mut snmp = try!(self.net_snmp());
+        match snmp.remove(name) {
+            Some(section) => Ok(section),
+            None => Err(ProcError::NotFound(format!("{}: {}", self.path("net/snmp"), name))),
+        }
+    }
 
-    let _names = tcp.next().unwrap();
-    let values = tcp.next().unwrap().split_whitespace().collect::<Vec<&str>>();
-    assert_eq!(values.len(), 15);
+    pub fn udp_stat(&self) -> Result<UdpStat, ProcError> {
+        let snmp = try!(self.net_snmp());
+        udp_stat(&self.path("net/snmp"), &snmp)
+    }
+
+    pub fn tcp_ext_stat(&self) -> Result<TcpExtStat, ProcError> {
+        let snmp = try!(self.net_snmp());
+        tcp_ext_stat(&self.path("net/netstat"), &snmp)
+    }
+}
+
+// snmp and netstat come as pairs of lines per section: "Name: columns..."
+// followed by "Name: values...".
+pub fn parse_snmp(path: &str, contents: &str) -> Result<Snmp, ProcError> {
+    let mut snmp = Snmp::new();
+    let mut header: Option<(&str, Vec<&str>)> = None;
+
+    for (i, line) in contents.lines().enumerate() {
+        let n = i + 1;
+        let mut name_rest = line.splitn(2, ':');
+        let (name, rest) = match (name_rest.next(), name_rest.next()) {
+            (Some(name), Some(rest)) => (name, rest),
+            _ => continue,
+        };
+
+        match header.take() {
+            Some((header_name, columns)) if header_name == name => {
+                let values = rest.split_whitespace().collect::<Vec<&str>>();
+                if columns.len() != values.len() {
+                    return Err(ProcError::UnexpectedFormat(
+                        format!("{}: {} {} columns but {} values", path, columns.len(), name, values.len())))
+                }
+                let mut section = SnmpSection::new();
+                for (column, value) in columns.iter().zip(values.iter()) {
+                    section.insert(String::from(*column), try!(parse_field(path, n, column, value)));
+                }
+                snmp.insert(String::from(name), section);
+            },
+            Some((header_name, _)) => return Err(ProcError::UnexpectedFormat(
+                format!("{}: no {} value line", path, header_name))),
+            None => header = Some((name, rest.split_whitespace().collect())),
+        }
+    }
+
+    match header {
+        Some((header_name, _)) => Err(ProcError::UnexpectedFormat(
+            format!("{}: no {} value line", path, header_name))),
+        None => Ok(snmp),
+    }
+}
+
+// snmp6 has a "Ip6InReceives 42" line per counter; the section is the name
+// up to and including the 6.
+pub fn parse_snmp6(path: &str, contents: &str) -> Result<Snmp, ProcError> {
+    let mut snmp = Snmp::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let mut kv = line.split_whitespace();
+        let (key, value) = match (kv.next(), kv.next()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => continue,
+        };
+        let (section, counter) = match key.find('6') {
+            Some(i) => (&key[..i + 1], &key[i + 1..]),
+            None => return Err(ProcError::parse(path, i + 1, key)),
+        };
+
+        snmp.entry(String::from(section)).or_insert_with(SnmpSection::new)
+            .insert(String::from(counter), try!(parse_field(path, i + 1, key, value)));
+    }
+
+    Ok(snmp)
+}
+
+fn section<'a>(path: &str, snmp: &'a Snmp, name: &str) -> Result<&'a SnmpSection, ProcError> {
+    match snmp.get(name) {
+        Some(section) => Ok(section),
+        None => Err(ProcError::UnexpectedFormat(format!("{}: no {} section", path, name))),
+    }
+}
+
+fn counter(path: &str, section: &SnmpSection, section_name: &str, name: &str)
+    -> Result<i64, ProcError> {
+    match section.get(name) {
+        Some(value) => Ok(*value),
+        None => Err(ProcError::UnexpectedFormat(
+            format!("{}: no {} in {}", path, name, section_name))),
+    }
+}
+
+// Counters added in later kernels read as 0 when missing.
+fn optional_counter(section: &SnmpSection, name: &str) -> usize {
+    section.get(name).map_or(0, |v| *v as usize)
+}
+
+pub fn udp_stat(path: &str, snmp: &Snmp) -> Result<UdpStat, ProcError> {
+    let udp = try!(section(path, snmp, "Udp"));
+
+    Ok(UdpStat {
+        in_datagrams: try!(counter(path, udp, "Udp", "InDatagrams")) as usize,
+        no_ports: try!(counter(path, udp, "Udp", "NoPorts")) as usize,
+        in_errors: try!(counter(path, udp, "Udp", "InErrors")) as usize,
+        out_datagrams: try!(counter(path, udp, "Udp", "OutDatagrams")) as usize,
+        rcvbuf_errors: optional_counter(udp, "RcvbufErrors"),
+        sndbuf_errors: optional_counter(udp, "SndbufErrors"),
+        in_csum_errors: optional_counter(udp, "InCsumErrors"),
+    })
+}
+
+pub fn tcp_ext_stat(path: &str, snmp: &Snmp) -> Result<TcpExtStat, ProcError> {
+    let ext = try!(section(path, snmp, "TcpExt"));
+
+    Ok(TcpExtStat {
+        listen_overflows: try!(counter(path, ext, "TcpExt", "ListenOverflows")) as usize,
+        listen_drops: try!(counter(path, ext, "TcpExt", "ListenDrops")) as usize,
+        tcp_timeouts: try!(counter(path, ext, "TcpExt", "TCPTimeouts")) as usize,
+        tcp_syn_retrans: optional_counter(ext, "TCPSynRetrans"),
+        tcp_backlog_drop: optional_counter(ext, "TCPBacklogDrop"),
+        tcp_abort_on_memory: optional_counter(ext, "TCPAbortOnMemory"),
+        tcp_abort_on_timeout: optional_counter(ext, "TCPAbortOnTimeout"),
+    })
+}
+
+// Columns are looked up by name so that kernels which append extra counters
+// (e.g. InCsumErrors) still parse.
+fn parse_tcp(path: &str, snmp: &str) -> Result<TcpStat, ProcError> {
+    let snmp = try!(parse_snmp(path, snmp));
+    let tcp = try!(section(path, &snmp, "Tcp"));
+
+    macro_rules! field {
+        ($name:expr) => (try!(counter(path, tcp, "Tcp", $name)))
+    }
 
     let stats = TcpStat{
-        rto_algorithm: i8::from_str(values[0]).unwrap(),
-        rto_min: isize::from_str(values[1]).unwrap(),
-        rto_max: isize::from_str(values[2]).unwrap(),
-        max_conn: isize::from_str(values[3]).unwrap(),
-        active_opens: usize::from_str(values[4]).unwrap(),
-        passive_opens: usize::from_str(values[5]).unwrap(),
-        attempt_fails: usize::from_str(values[6]).unwrap(),
-        establish_resets: usize::from_str(values[7]).unwrap(),
-        current_establish: usize::from_str(values[8]).unwrap(),
-        segments_received: usize::from_str(values[9]).unwrap(),
-        segments_sent: usize::from_str(values[10]).unwrap(),
-        segments_retransmitted: usize::from_str(values[11]).unwrap(),
-        segments_errors_received: usize::from_str(values[12]).unwrap(),
-        resets_sent: usize::from_str(values[13]).unwrap(),
+        rto_algorithm: field!("RtoAlgorithm") as i8,
+        rto_min: field!("RtoMin") as isize,
+        rto_max: field!("RtoMax") as isize,
+        max_conn: field!("MaxConn") as isize,
+        active_opens: field!("ActiveOpens") as usize,
+        passive_opens: field!("PassiveOpens") as usize,
+        attempt_fails: field!("AttemptFails") as usize,
+        establish_resets: field!("EstabResets") as usize,
+        current_establish: field!("CurrEstab") as usize,
+        segments_received: field!("InSegs") as usize,
+        segments_sent: field!("OutSegs") as usize,
+        segments_retransmitted: field!("RetransSegs") as usize,
+        segments_errors_received: field!("InErrs") as usize,
+        resets_sent: field!("OutRsts") as usize,
     };
 
     Ok(stats)
@@ -59,10 +285,124 @@ pub fn process_tcp<'a>() -> io::Result<TcpStat> {
 #[cfg(test)]
 mod test {
     use proc_fs::net::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+    use super::parse_tcp;
 
     #[test]
     fn test_proc_stack() {
-        let tcp = process_tcp();
+        let tcp = ProcFs::default().process_tcp();
         assert!(tcp.is_ok());
     }
+
+    #[test]
+    fn test_proc_tcp_fixture() {
+        let tcp = fixtures().process_tcp().unwrap();
+        assert_eq!(tcp.active_opens, 102);
+        assert_eq!(tcp.current_establish, 2);
+    }
+
+    #[test]
+    fn test_parse_tcp_extra_column() {
+        let snmp = "Ip: Forwarding DefaultTTL\n\
+                    Ip: 1 64\n\
+                    Tcp: RtoAlgorithm RtoMin RtoMax MaxConn ActiveOpens PassiveOpens \
+                    AttemptFails EstabResets CurrEstab InSegs OutSegs RetransSegs InErrs \
+                    OutRsts InCsumErrors SomethingNew\n\
+                    Tcp: 1 200 120000 -1 10 5 1 2 3 100 90 4 0 6 0 7\n";
+        let tcp = parse_tcp("/proc/net/snmp", snmp).unwrap();
+        assert_eq!(tcp.max_conn, -1);
+        assert_eq!(tcp.segments_retransmitted, 4);
+        assert_eq!(tcp.resets_sent, 6);
+
+        assert!(parse_tcp("/proc/net/snmp", "Tcp: RtoAlgorithm RtoMin\nTcp: 1\n").is_err());
+        assert!(parse_tcp("/proc/net/snmp", "Ip: Forwarding\nIp: 1\n").is_err());
+    }
+
+    #[test]
+    fn test_process_network() {
+        let network = ProcFs::default().process_network(Pid::Current).unwrap();
+        assert!(network.net_ns.is_some());
+        assert!(network.interfaces.iter().any(|i| i.name == "lo"));
+    }
+
+    #[test]
+    fn test_process_network_fixture() {
+        assert_eq!(fixtures().process_net_namespace(Pid::Id(413)).unwrap(), 4026531992);
+
+        let network = fixtures().process_network(Pid::Id(414)).unwrap();
+        assert_eq!(network.net_ns, Some(4026532281));
+        assert_eq!(network.tcp.passive_opens, 311);
+        assert_eq!(network.tcp.current_establish, 14);
+        assert_eq!(network.interfaces.len(), 2);
+        assert_eq!(network.interfaces[1].rx.bytes, 4412093);
+
+        let network = fixtures().process_network(Pid::Id(412)).unwrap();
+        assert_eq!(network.tcp, fixtures().process_tcp().unwrap());
+        assert!(fixtures().process_network(Pid::Id(999)).is_err());
+    }
+
+    #[test]
+    fn test_net_snmp() {
+        let snmp = ProcFs::default().net_snmp().unwrap();
+        assert!(snmp.contains_key("Tcp"));
+        assert!(snmp.contains_key("TcpExt"));
+        assert!(ProcFs::default().udp_stat().is_ok());
+        assert!(ProcFs::default().tcp_ext_stat().is_ok());
+    }
+
+    #[test]
+    fn test_net_snmp_fixture() {
+        let snmp = fixtures().net_snmp().unwrap();
+        let sections = snmp.keys().map(|k| k.as_str()).collect::<Vec<&str>>();
+        assert_eq!(sections, vec!["Icmp", "Icmp6", "IcmpMsg", "Ip", "Ip6", "IpExt", "MPTcpExt",
+                                  "Tcp", "TcpExt", "Udp", "Udp6", "UdpLite", "UdpLite6"]);
+        assert_eq!(snmp["Tcp"]["MaxConn"], -1);
+        assert_eq!(snmp["IcmpMsg"]["OutType3"], 12);
+        assert_eq!(snmp["IpExt"]["InOctets"], 9138471923);
+        assert_eq!(snmp["Icmp6"]["OutType133"], 4);
+        assert_eq!(snmp["Udp6"]["InDatagrams"], 920);
+
+        assert_eq!(fixtures().net_snmp_section("Udp6").unwrap()["RcvbufErrors"], 7);
+        assert!(fixtures().net_snmp_section("Nope").is_err());
+    }
+
+    #[test]
+    fn test_udp_stat_fixture() {
+        let udp = fixtures().udp_stat().unwrap();
+        assert_eq!(udp.in_datagrams, 18822);
+        assert_eq!(udp.no_ports, 4);
+        assert_eq!(udp.in_errors, 37);
+        assert_eq!(udp.rcvbuf_errors, 35);
+        assert_eq!(udp.in_csum_errors, 2);
+    }
+
+    #[test]
+    fn test_tcp_ext_stat_fixture() {
+        let ext = fixtures().tcp_ext_stat().unwrap();
+        assert_eq!(ext.listen_overflows, 1432);
+        assert_eq!(ext.listen_drops, 1437);
+        assert_eq!(ext.tcp_timeouts, 5821);
+        assert_eq!(ext.tcp_syn_retrans, 211);
+        assert_eq!(ext.tcp_backlog_drop, 3);
+    }
+
+    #[test]
+    fn test_parse_snmp() {
+        let snmp = parse_snmp("netstat", "TcpExt: SyncookiesSent ListenDrops\n\
+                                          TcpExt: 0 12\n").unwrap();
+        assert_eq!(snmp["TcpExt"]["ListenDrops"], 12);
+        assert!(udp_stat("netstat", &snmp).is_err());
+
+        assert!(parse_snmp("netstat", "TcpExt: SyncookiesSent ListenDrops\n").is_err());
+        assert!(parse_snmp("netstat", "TcpExt: ListenDrops\nIpExt: 1\n").is_err());
+        assert!(parse_snmp("netstat", "TcpExt: ListenDrops\nTcpExt: x\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_snmp6() {
+        let snmp = parse_snmp6("snmp6", "Ip6InReceives   3\nUdpLite6InErrors 1\n").unwrap();
+        assert_eq!(snmp["Ip6"]["InReceives"], 3);
+        assert_eq!(snmp["UdpLite6"]["InErrors"], 1);
+        assert!(parse_snmp6("snmp6", "InReceives 3\n").is_err());
+    }
 }
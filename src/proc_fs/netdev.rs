@@ -0,0 +1,336 @@
+/*
+ * Network interface counters from /proc/net/dev
+ */
+use std::thread;
+
+use regex::Regex;
+use time::precise_time_ns;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field};
+
+// All counters are since the interface came up.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct RxCounters {
+    pub bytes: usize,
+    pub packets: usize,
+    pub errs: usize,
+    pub drop: usize,
+    pub fifo: usize, // ring buffer overruns
+    pub frame: usize, // framing errors
+    pub compressed: usize,
+    pub multicast: usize,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct TxCounters {
+    pub bytes: usize,
+    pub packets: usize,
+    pub errs: usize,
+    pub drop: usize,
+    pub fifo: usize,
+    pub colls: usize, // collisions
+    pub carrier: usize, // carrier losses
+    pub compressed: usize,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct NetDevice {
+    pub name: String,
+    pub rx: RxCounters,
+    pub tx: TxCounters,
+}
+
+// Per second rates between two samples of the same interface.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct InterfaceRates {
+    pub rx_bytes: f64,
+    pub rx_packets: f64,
+    pub rx_errs: f64,
+    pub rx_drop: f64,
+    pub rx_fifo: f64,
+    pub rx_frame: f64,
+    pub rx_compressed: f64,
+    pub rx_multicast: f64,
+    pub tx_bytes: f64,
+    pub tx_packets: f64,
+    pub tx_errs: f64,
+    pub tx_drop: f64,
+    pub tx_fifo: f64,
+    pub tx_colls: f64,
+    pub tx_carrier: f64,
+    pub tx_compressed: f64,
+    pub window_secs: f64,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Interface {
+    pub device: NetDevice,
+    pub rates: Option<InterfaceRates>, // None on the first sample or for a new interface
+}
+
+/// Which interfaces to report. With neither set every interface is kept.
+#[derive(Clone, Debug, Default)]
+pub struct InterfaceFilter {
+    pub include: Option<Regex>, // keep only the matching names
+    pub exclude: Option<Regex>, // then drop the matching names, e.g. ^(lo|veth)
+}
+
+impl InterfaceFilter {
+    pub fn matches(&self, name: &str) -> bool {
+        self.include.as_ref().map_or(true, |r| r.is_match(name)) &&
+            !self.exclude.as_ref().map_or(false, |r| r.is_match(name))
+    }
+
+    pub fn apply(&self, devices: Vec<NetDevice>) -> Vec<NetDevice> {
+        devices.into_iter().filter(|d| self.matches(&d.name)).collect()
+    }
+}
+
+// After the two header lines every line is "name: 8 rx counters 8 tx
+// counters". Old kernels leave no space after the colon.
+pub fn parse_net_dev(path: &str, dev: &str) -> Result<Vec<NetDevice>, ProcError> {
+    let mut devices = Vec::new();
+
+    for (i, line) in dev.lines().enumerate().skip(2) {
+        let n = i + 1;
+        let mut name_counters = line.splitn(2, ':');
+        let (name, counters) = match (name_counters.next(), name_counters.next()) {
+            (Some(name), Some(counters)) => (name.trim(), counters),
+            _ => return Err(ProcError::parse(path, n, "interface")),
+        };
+
+        let fields = counters.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() < 16 {
+            return Err(ProcError::UnexpectedFormat(
+                format!("{}: {} counters for {}", path, fields.len(), name)))
+        }
+        let mut values = [0; 16];
+        for (j, value) in fields.iter().take(16).enumerate() {
+            values[j] = try!(parse_field(path, n, name, value));
+        }
+
+        devices.push(NetDevice {
+            name: String::from(name),
+            rx: RxCounters {
+                bytes: values[0],
+                packets: values[1],
+                errs: values[2],
+                drop: values[3],
+                fifo: values[4],
+                frame: values[5],
+                compressed: values[6],
+                multicast: values[7],
+            },
+            tx: TxCounters {
+                bytes: values[8],
+                packets: values[9],
+                errs: values[10],
+                drop: values[11],
+                fifo: values[12],
+                colls: values[13],
+                carrier: values[14],
+                compressed: values[15],
+            },
+        });
+    }
+
+    Ok(devices)
+}
+
+pub fn interface_rates(prev: &NetDevice, cur: &NetDevice, window_secs: f64) -> InterfaceRates {
+    let rate = |prev: usize, cur: usize| {
+        if window_secs <= 0.0 { 0.0 } else { cur.saturating_sub(prev) as f64 / window_secs }
+    };
+
+    InterfaceRates {
+        rx_bytes: rate(prev.rx.bytes, cur.rx.bytes),
+        rx_packets: rate(prev.rx.packets, cur.rx.packets),
+        rx_errs: rate(prev.rx.errs, cur.rx.errs),
+        rx_drop: rate(prev.rx.drop, cur.rx.drop),
+        rx_fifo: rate(prev.rx.fifo, cur.rx.fifo),
+        rx_frame: rate(prev.rx.frame, cur.rx.frame),
+        rx_compressed: rate(prev.rx.compressed, cur.rx.compressed),
+        rx_multicast: rate(prev.rx.multicast, cur.rx.multicast),
+        tx_bytes: rate(prev.tx.bytes, cur.tx.bytes),
+        tx_packets: rate(prev.tx.packets, cur.tx.packets),
+        tx_errs: rate(prev.tx.errs, cur.tx.errs),
+        tx_drop: rate(prev.tx.drop, cur.tx.drop),
+        tx_fifo: rate(prev.tx.fifo, cur.tx.fifo),
+        tx_colls: rate(prev.tx.colls, cur.tx.colls),
+        tx_carrier: rate(prev.tx.carrier, cur.tx.carrier),
+        tx_compressed: rate(prev.tx.compressed, cur.tx.compressed),
+        window_secs: window_secs,
+    }
+}
+
+/// Pairs every current device with its previous sample by name.
+pub fn interfaces(prev: Option<&[NetDevice]>, cur: Vec<NetDevice>, window_secs: f64) -> Vec<Interface> {
+    cur.into_iter().map(|device| {
+        let rates = prev
+            .and_then(|prev| prev.iter().find(|p| p.name == device.name))
+            .map(|p| interface_rates(p, &device, window_secs));
+        Interface {
+            device: device,
+            rates: rates,
+        }
+    }).collect()
+}
+
+impl ProcFs {
+    pub fn net_dev(&self) -> Result<Vec<NetDevice>, ProcError> {
+        let path = self.path("net/dev");
+        let dev = try!(read_proc_file(&path));
+
+        parse_net_dev(&path, &dev)
+    }
+
+    /// Interfaces of the network namespace the process is in.
+    pub fn process_net_dev(&self, pid: Pid) -> Result<Vec<NetDevice>, ProcError> {
+        let path = self.path(&format!("{}/net/dev", pid));
+        let dev = try!(read_proc_file(&path));
+
+        parse_net_dev(&path, &dev)
+    }
+
+    /// Takes two samples `window_ms` apart and reports the matching
+    /// interfaces with their rates in between.
+    pub fn net_interfaces(&self, filter: &InterfaceFilter, window_ms: u32)
+        -> Result<Vec<Interface>, ProcError> {
+        let first = filter.apply(try!(self.net_dev()));
+        let start = precise_time_ns();
+        thread::sleep_ms(window_ms);
+        let second = filter.apply(try!(self.net_dev()));
+        let window_secs = (precise_time_ns() - start) as f64 / 1e9;
+
+        Ok(interfaces(Some(&first), second, window_secs))
+    }
+}
+
+/// Keeps the previous sample around so that a periodic caller gets rates
+/// since its last call.
+pub struct InterfaceSampler {
+    proc_fs: ProcFs,
+    filter: InterfaceFilter,
+    last: Option<(Vec<NetDevice>, u64)>, // with the precise_time_ns it was taken at
+}
+
+impl InterfaceSampler {
+    pub fn new(proc_fs: ProcFs, filter: InterfaceFilter) -> InterfaceSampler {
+        InterfaceSampler {
+            proc_fs: proc_fs,
+            filter: filter,
+            last: None,
+        }
+    }
+
+    pub fn sample(&mut self) -> Result<Vec<Interface>, ProcError> {
+        let cur = self.filter.apply(try!(self.proc_fs.net_dev()));
+        let now = precise_time_ns();
+        let result = match self.last {
+            Some((ref prev, then)) => interfaces(Some(prev), cur.clone(), (now - then) as f64 / 1e9),
+            None => interfaces(None, cur.clone(), 0.0),
+        };
+        self.last = Some((cur, now));
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use regex::Regex;
+    use proc_fs::netdev::*;
+    use proc_fs::{ProcFs, fixtures};
+
+    fn device(name: &str, rx_bytes: usize, tx_bytes: usize) -> NetDevice {
+        NetDevice {
+            name: String::from(name),
+            rx: RxCounters { bytes: rx_bytes, ..RxCounters::default() },
+            tx: TxCounters { bytes: tx_bytes, ..TxCounters::default() },
+        }
+    }
+
+    #[test]
+    fn test_net_dev() {
+        let devices = ProcFs::default().net_dev().unwrap();
+        assert!(devices.iter().any(|d| d.name == "lo"));
+    }
+
+    #[test]
+    fn test_net_dev_fixture() {
+        let devices = fixtures().net_dev().unwrap();
+        let names = devices.iter().map(|d| d.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["lo", "eth0", "docker0", "veth3f2a1c0"]);
+
+        let eth0 = &devices[1];
+        assert_eq!(eth0.rx.bytes, 9138471923);
+        assert_eq!(eth0.rx.packets, 7124830);
+        assert_eq!(eth0.rx.errs, 12);
+        assert_eq!(eth0.rx.drop, 340);
+        assert_eq!(eth0.rx.frame, 3);
+        assert_eq!(eth0.rx.multicast, 18234);
+        assert_eq!(eth0.tx.bytes, 1473920133);
+        assert_eq!(devices[2].tx.drop, 4);
+    }
+
+    #[test]
+    fn test_parse_net_dev_no_space() {
+        let dev = "header\nheader\n\
+                   eth0:100 2 0 0 0 0 0 0 200 3 0 0 0 0 0 0\n";
+        let devices = parse_net_dev("dev", dev).unwrap();
+        assert_eq!(devices[0].name, "eth0");
+        assert_eq!(devices[0].rx.bytes, 100);
+        assert_eq!(devices[0].tx.packets, 3);
+
+        assert!(parse_net_dev("dev", "header\nheader\neth0: 1 2 3\n").is_err());
+    }
+
+    #[test]
+    fn test_interface_filter() {
+        let devices = fixtures().net_dev().unwrap();
+
+        let filter = InterfaceFilter {
+            include: None,
+            exclude: Some(Regex::new("^(lo|veth)").unwrap()),
+        };
+        let names = filter.apply(devices.clone()).into_iter().map(|d| d.name).collect::<Vec<String>>();
+        assert_eq!(names, vec!["eth0", "docker0"]);
+
+        let filter = InterfaceFilter {
+            include: Some(Regex::new("^(eth|docker)").unwrap()),
+            exclude: Some(Regex::new("^docker").unwrap()),
+        };
+        assert_eq!(filter.apply(devices.clone()).len(), 1);
+        assert_eq!(InterfaceFilter::default().apply(devices).len(), 4);
+    }
+
+    #[test]
+    fn test_interface_rates() {
+        let prev = vec![device("eth0", 1000, 500)];
+        let mut eth0 = device("eth0", 5000, 700);
+        eth0.rx = RxCounters { fifo: 8, frame: 4, compressed: 2, multicast: 40, ..eth0.rx };
+        eth0.tx = TxCounters { fifo: 6, colls: 10, carrier: 2, compressed: 4, ..eth0.tx };
+        let cur = vec![eth0, device("eth1", 10, 10)];
+
+        let result = interfaces(Some(&prev), cur, 2.0);
+        let rates = result[0].rates.as_ref().unwrap();
+        assert_eq!(rates.rx_bytes, 2000.0);
+        assert_eq!(rates.rx_fifo, 4.0);
+        assert_eq!(rates.rx_frame, 2.0);
+        assert_eq!(rates.rx_compressed, 1.0);
+        assert_eq!(rates.rx_multicast, 20.0);
+        assert_eq!(rates.tx_bytes, 100.0);
+        assert_eq!(rates.tx_fifo, 3.0);
+        assert_eq!(rates.tx_colls, 5.0);
+        assert_eq!(rates.tx_carrier, 1.0);
+        assert_eq!(rates.tx_compressed, 2.0);
+        assert_eq!(rates.window_secs, 2.0);
+        assert_eq!(result[1].rates, None);
+    }
+
+    #[test]
+    fn test_interface_sampler() {
+        let mut sampler = InterfaceSampler::new(ProcFs::default(), InterfaceFilter::default());
+        assert!(sampler.sample().unwrap().iter().all(|i| i.rates.is_none()));
+        assert!(sampler.sample().unwrap().iter().all(|i| i.rates.is_some()));
+    }
+}
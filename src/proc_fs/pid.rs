@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+
+/// A process to read from /proc. Only positive process ids and the `self` and
+/// `thread-self` links are accepted, so a pid can always be used as a single
+/// path component.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Pid {
+    Id(u32),
+    Current, // /proc/self
+    CurrentThread, // /proc/thread-self
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParsePidError {
+    input: String,
+}
+
+impl Error for ParsePidError {
+    fn description(&self) -> &str {
+        "invalid pid"
+    }
+}
+
+impl fmt::Display for ParsePidError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "invalid pid: {:?}", self.input)
+    }
+}
+
+impl FromStr for Pid {
+    type Err = ParsePidError;
+
+    fn from_str(s: &str) -> Result<Pid, ParsePidError> {
+        match s {
+            "self" => Ok(Pid::Current),
+            "thread-self" => Ok(Pid::CurrentThread),
+            // u32::from_str accepts a leading '+', which is not a valid path
+            _ if s.bytes().all(|b| b >= b'0' && b <= b'9') => {
+                match u32::from_str(s) {
+                    Ok(id) if id > 0 => Ok(Pid::Id(id)),
+                    _ => Err(ParsePidError { input: String::from(s) }),
+                }
+            },
+            _ => Err(ParsePidError { input: String::from(s) }),
+        }
+    }
+}
+
+impl fmt::Display for Pid {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Pid::Id(id) => write!(fmt, "{}", id),
+            Pid::Current => write!(fmt, "self"),
+            Pid::CurrentThread => write!(fmt, "thread-self"),
+        }
+    }
+}
+
+// Numeric pids serialize as numbers, the links as their names.
+impl Serialize for Pid {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer {
+        match *self {
+            Pid::Id(id) => serializer.visit_u32(id),
+            Pid::Current => serializer.visit_str("self"),
+            Pid::CurrentThread => serializer.visit_str("thread-self"),
+        }
+    }
+}
+
+struct PidVisitor;
+
+impl Visitor for PidVisitor {
+    type Value = Pid;
+
+    fn visit_u64<E>(&mut self, v: u64) -> Result<Pid, E> where E: de::Error {
+        if v > 0 && v <= u32::max_value() as u64 {
+            Ok(Pid::Id(v as u32))
+        } else {
+            Err(E::syntax("invalid pid"))
+        }
+    }
+
+    fn visit_i64<E>(&mut self, v: i64) -> Result<Pid, E> where E: de::Error {
+        if v > 0 {
+            self.visit_u64(v as u64)
+        } else {
+            Err(E::syntax("invalid pid"))
+        }
+    }
+
+    fn visit_str<E>(&mut self, v: &str) -> Result<Pid, E> where E: de::Error {
+        Pid::from_str(v).map_err(|_| E::syntax("invalid pid"))
+    }
+}
+
+impl Deserialize for Pid {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Pid, D::Error>
+        where D: Deserializer {
+        deserializer.visit(PidVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use serde_json;
+    use proc_fs::pid::*;
+
+    #[test]
+    fn test_parse_pid() {
+        assert_eq!(Pid::from_str("1234"), Ok(Pid::Id(1234)));
+        assert_eq!(Pid::from_str("self"), Ok(Pid::Current));
+        assert_eq!(Pid::from_str("thread-self"), Ok(Pid::CurrentThread));
+
+        assert!(Pid::from_str("0").is_err());
+        assert!(Pid::from_str("-1").is_err());
+        assert!(Pid::from_str("+1").is_err());
+        assert!(Pid::from_str("").is_err());
+        assert!(Pid::from_str("..").is_err());
+        assert!(Pid::from_str("../../etc").is_err());
+        assert!(Pid::from_str("99999999999").is_err());
+    }
+
+    #[test]
+    fn test_pid_display() {
+        for s in ["1234", "self", "thread-self"].iter() {
+            assert_eq!(format!("{}", Pid::from_str(s).unwrap()), *s);
+        }
+    }
+
+    #[test]
+    fn test_pid_serde() {
+        assert_eq!(serde_json::to_string(&Pid::Id(42)).unwrap(), "42");
+        assert_eq!(serde_json::to_string(&Pid::Current).unwrap(), "\"self\"");
+
+        let pid: Pid = serde_json::from_str("42").unwrap();
+        assert_eq!(pid, Pid::Id(42));
+        let pid: Pid = serde_json::from_str("\"thread-self\"").unwrap();
+        assert_eq!(pid, Pid::CurrentThread);
+        assert!(serde_json::from_str::<Pid>("0").is_err());
+    }
+}
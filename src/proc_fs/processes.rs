@@ -0,0 +1,265 @@
+/*
+ * Discover processes by scanning the numeric directories of /proc, like ps.
+ */
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::string::String;
+use std::str::FromStr;
+
+use regex::Regex;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, page_size};
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProcessSummary {
+    pub pid: u32,
+    pub ppid: u32,
+    pub comm: String,
+    pub state: char,
+    pub uid: Option<usize>, // effective uid
+    pub user: Option<String>, // None if the uid has no passwd entry
+    pub rss: usize, // bytes
+    pub cpu_ticks: usize, // utime + stime
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SortKey {
+    Pid,
+    Comm,
+    Rss, // largest first
+    Cpu, // busiest first
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SortKey, String> {
+        match s {
+            "pid" => Ok(SortKey::Pid),
+            "comm" => Ok(SortKey::Comm),
+            "rss" => Ok(SortKey::Rss),
+            "cpu" => Ok(SortKey::Cpu),
+            _ => Err(format!("unknown sort key '{}', expected pid, comm, rss or cpu", s)),
+        }
+    }
+}
+
+/// Which processes to list, and in what order.
+pub struct ProcessFilter {
+    pub user: Option<String>, // user name or numeric uid
+    pub command: Option<Regex>, // matched against comm
+    pub state: Option<char>,
+    pub sort: SortKey,
+    pub limit: Option<usize>,
+}
+
+impl Default for ProcessFilter {
+    fn default() -> ProcessFilter {
+        ProcessFilter {
+            user: None,
+            command: None,
+            state: None,
+            sort: SortKey::Pid,
+            limit: None,
+        }
+    }
+}
+
+impl ProcessFilter {
+    pub fn matches(&self, process: &ProcessSummary) -> bool {
+        if let Some(ref user) = self.user {
+            let by_name = process.user.as_ref().map_or(false, |u| u == user);
+            let by_uid = process.uid.map_or(false, |uid| format!("{}", uid) == *user);
+            if !by_name && !by_uid {
+                return false
+            }
+        }
+        if let Some(ref command) = self.command {
+            if !command.is_match(&process.comm) {
+                return false
+            }
+        }
+        if let Some(state) = self.state {
+            if process.state != state {
+                return false
+            }
+        }
+        true
+    }
+
+    pub fn apply(&self, processes: Vec<ProcessSummary>) -> Vec<ProcessSummary> {
+        let mut selected = processes.into_iter()
+            .filter(|p| self.matches(p))
+            .collect::<Vec<ProcessSummary>>();
+
+        selected.sort_by(|a, b| match self.sort {
+            SortKey::Pid => a.pid.cmp(&b.pid),
+            SortKey::Comm => a.comm.cmp(&b.comm).then_pid(a, b),
+            SortKey::Rss => b.rss.cmp(&a.rss).then_pid(a, b),
+            SortKey::Cpu => b.cpu_ticks.cmp(&a.cpu_ticks).then_pid(a, b),
+        });
+
+        if let Some(limit) = self.limit {
+            selected.truncate(limit);
+        }
+        selected
+    }
+}
+
+// Ties are broken by pid so listings are stable between requests.
+trait ThenPid {
+    fn then_pid(self, a: &ProcessSummary, b: &ProcessSummary) -> Ordering;
+}
+
+impl ThenPid for Ordering {
+    fn then_pid(self, a: &ProcessSummary, b: &ProcessSummary) -> Ordering {
+        match self {
+            Ordering::Equal => a.pid.cmp(&b.pid),
+            other => other,
+        }
+    }
+}
+
+// passwd lines look like name:password:uid:gid:gecos:home:shell
+fn parse_passwd(passwd: &str) -> BTreeMap<usize, String> {
+    let mut users = BTreeMap::new();
+    for line in passwd.lines() {
+        let fields = line.split(':').collect::<Vec<&str>>();
+        if fields.len() < 3 {
+            continue
+        }
+        if let Ok(uid) = usize::from_str(fields[2]) {
+            users.entry(uid).or_insert(String::from(fields[0]));
+        }
+    }
+    users
+}
+
+impl ProcFs {
+    /// Every process currently under the proc root, in pid order.
+    pub fn pids(&self) -> Result<Vec<u32>, ProcError> {
+        let dir = self.path("");
+        let entries = try!(fs::read_dir(&dir).map_err(|e| ProcError::from_io(&dir, e)));
+
+        let mut pids = Vec::new();
+        for entry in entries {
+            let entry = try!(entry.map_err(|e| ProcError::from_io(&dir, e)));
+            if let Ok(pid) = u32::from_str(&entry.file_name().to_string_lossy()) {
+                pids.push(pid);
+            }
+        }
+
+        pids.sort();
+        Ok(pids)
+    }
+
+    /// User names by uid, from the passwd file next to the proc root, so a
+    /// host's /proc mounted at /host/proc is paired with /host/etc/passwd.
+    /// Empty if there is no such file.
+    pub fn users(&self) -> BTreeMap<usize, String> {
+        let path = self.path("../etc/passwd");
+        match read_proc_file(&path) {
+            Ok(passwd) => parse_passwd(&passwd),
+            Err(_) => BTreeMap::new(),
+        }
+    }
+
+    pub fn process_summary(&self, pid: Pid, users: &BTreeMap<usize, String>)
+        -> Result<ProcessSummary, ProcError> {
+        let stat = try!(self.process_stat(pid));
+        let status = try!(self.process_status(pid));
+        let uid = status.uid.map(|ids| ids.effective);
+
+        Ok(ProcessSummary {
+            pid: stat.pid as u32,
+            ppid: stat.ppid as u32,
+            comm: stat.command,
+            state: stat.state,
+            uid: uid,
+            user: uid.and_then(|uid| users.get(&uid).map(|u| u.clone())),
+            rss: stat.rss as usize * page_size(),
+            cpu_ticks: stat.utime + stat.stime,
+        })
+    }
+
+    /// A summary of every process. Processes that exit while we are
+    /// scanning are skipped.
+    pub fn processes(&self) -> Result<Vec<ProcessSummary>, ProcError> {
+        let users = self.users();
+        let mut processes = Vec::new();
+
+        for pid in try!(self.pids()) {
+            match self.process_summary(Pid::Id(pid), &users) {
+                Ok(summary) => processes.push(summary),
+                Err(ProcError::NotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(processes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libc;
+    use regex::Regex;
+    use proc_fs::processes::*;
+    use proc_fs::{ProcFs, fixtures, page_size};
+
+    #[test]
+    fn test_processes() {
+        let processes = ProcFs::default().processes().unwrap();
+        let pid = unsafe { libc::getpid() } as u32;
+        assert!(processes.iter().any(|p| p.pid == pid));
+    }
+
+    #[test]
+    fn test_processes_fixture() {
+        let processes = fixtures().processes().unwrap();
+        assert_eq!(processes.iter().map(|p| p.pid).collect::<Vec<u32>>(),
+                   vec![1, 412, 413, 414]);
+
+        assert_eq!(processes[0].user, Some(String::from("root")));
+        assert_eq!(processes[2].comm, "worker");
+        assert_eq!(processes[2].ppid, 412);
+        assert_eq!(processes[2].state, 'R');
+        assert_eq!(processes[2].uid, Some(1000));
+        assert_eq!(processes[2].user, Some(String::from("app")));
+        assert_eq!(processes[2].rss, 25600 * page_size());
+        assert_eq!(processes[2].cpu_ticks, 90000 + 12000);
+    }
+
+    #[test]
+    fn test_process_filter() {
+        let processes = fixtures().processes().unwrap();
+
+        let filter = ProcessFilter {
+            user: Some(String::from("app")),
+            command: Some(Regex::new("^work").unwrap()),
+            ..ProcessFilter::default()
+        };
+        let selected = filter.apply(processes.clone());
+        assert_eq!(selected.iter().map(|p| p.pid).collect::<Vec<u32>>(), vec![413, 414]);
+
+        let filter = ProcessFilter {
+            user: Some(String::from("1000")),
+            state: Some('S'),
+            ..ProcessFilter::default()
+        };
+        let selected = filter.apply(processes.clone());
+        assert_eq!(selected.iter().map(|p| p.pid).collect::<Vec<u32>>(), vec![412, 414]);
+
+        let filter = ProcessFilter {
+            sort: SortKey::Rss,
+            limit: Some(2),
+            ..ProcessFilter::default()
+        };
+        let selected = filter.apply(processes);
+        assert_eq!(selected.iter().map(|p| p.pid).collect::<Vec<u32>>(), vec![413, 414]);
+
+        assert_eq!("cpu".parse::<SortKey>(), Ok(SortKey::Cpu));
+        assert!("size".parse::<SortKey>().is_err());
+    }
+}
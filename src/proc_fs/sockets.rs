@@ -0,0 +1,505 @@
+/*
+ * Socket tables from /proc/net/{tcp,tcp6,udp,udp6,unix}, joined with the
+ * socket links under /proc/<pid>/fd to find which process holds what.
+ */
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::string::String;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field};
+use proc_fs::fds::FdKind;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Protocol {
+    Tcp,
+    Tcp6,
+    Udp,
+    Udp6,
+    Unix,
+}
+
+pub const PROTOCOLS: [Protocol; 5] =
+    [Protocol::Tcp, Protocol::Tcp6, Protocol::Udp, Protocol::Udp6, Protocol::Unix];
+
+impl Protocol {
+    // Name of the table under net/.
+    pub fn file(&self) -> &'static str {
+        match *self {
+            Protocol::Tcp => "tcp",
+            Protocol::Tcp6 => "tcp6",
+            Protocol::Udp => "udp",
+            Protocol::Udp6 => "udp6",
+            Protocol::Unix => "unix",
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Protocol, String> {
+        match PROTOCOLS.iter().find(|p| p.file() == s) {
+            Some(protocol) => Ok(*protocol),
+            None => Err(format!("unknown protocol '{}', expected tcp, tcp6, udp, udp6 or unix", s)),
+        }
+    }
+}
+
+// The TCP states of include/net/tcp_states.h. UDP sockets are Established
+// when connected and Close otherwise, unix sockets are mapped onto the same
+// states the way ss does.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SocketState {
+    Established,
+    SynSent,
+    SynRecv,
+    FinWait1,
+    FinWait2,
+    TimeWait,
+    Close,
+    CloseWait,
+    LastAck,
+    Listen,
+    Closing,
+    NewSynRecv,
+    Unknown,
+}
+
+const STATES: [(SocketState, &'static str); 12] = [
+    (SocketState::Established, "established"),
+    (SocketState::SynSent, "syn-sent"),
+    (SocketState::SynRecv, "syn-recv"),
+    (SocketState::FinWait1, "fin-wait-1"),
+    (SocketState::FinWait2, "fin-wait-2"),
+    (SocketState::TimeWait, "time-wait"),
+    (SocketState::Close, "close"),
+    (SocketState::CloseWait, "close-wait"),
+    (SocketState::LastAck, "last-ack"),
+    (SocketState::Listen, "listen"),
+    (SocketState::Closing, "closing"),
+    (SocketState::NewSynRecv, "new-syn-recv"),
+];
+
+impl SocketState {
+    fn from_code(code: u8) -> SocketState {
+        match code {
+            1...12 => STATES[code as usize - 1].0,
+            _ => SocketState::Unknown,
+        }
+    }
+}
+
+// State names as ss prints them, e.g. "listen" or "time-wait".
+impl FromStr for SocketState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SocketState, String> {
+        match STATES.iter().find(|&&(_, name)| name == s) {
+            Some(&(state, _)) => Ok(state),
+            None => Err(format!("unknown socket state '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Socket {
+    pub protocol: Protocol,
+    pub local_address: String, // empty for unix sockets
+    pub local_port: u16,
+    pub remote_address: String,
+    pub remote_port: u16,
+    pub path: Option<String>, // unix sockets only, abstract names start with @
+    pub state: SocketState,
+    pub tx_queue: usize, // bytes, always 0 when listening
+    pub rx_queue: usize, // bytes, or connections waiting for accept when listening
+    pub timer: u8, // 0 none, 1 retransmit, 2 keepalive, 3 time wait, 4 zero window probe
+    pub timer_expires: usize, // clock ticks
+    pub retransmits: usize, // unrecovered retransmission timeouts
+    pub uid: Option<usize>, // not known for unix sockets
+    pub inode: u64, // 0 once the socket has no file, e.g. in time wait
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProcessSocket {
+    pub fd: usize,
+    pub socket: Socket,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SocketOwner {
+    pub pid: u32,
+    pub comm: String,
+    pub fd: usize,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Connection {
+    pub socket: Socket,
+    pub owners: Vec<SocketOwner>, // empty if unknown, e.g. without permission
+}
+
+/// Which sockets to list. A port matches either end of the connection.
+#[derive(Clone, Debug, Default)]
+pub struct SocketFilter {
+    pub protocol: Option<Protocol>,
+    pub state: Option<SocketState>,
+    pub port: Option<u16>,
+}
+
+impl SocketFilter {
+    pub fn matches(&self, socket: &Socket) -> bool {
+        self.protocol.map_or(true, |p| p == socket.protocol) &&
+            self.state.map_or(true, |s| s == socket.state) &&
+            self.port.map_or(true, |p| p == socket.local_port || p == socket.remote_port)
+    }
+}
+
+// Most columns are hex; `parse` is the from_str_radix of the type wanted.
+fn parse_hex<T>(path: &str, line: usize, field: &str, value: &str,
+                parse: fn(&str, u32) -> Result<T, ParseIntError>) -> Result<T, ProcError> {
+    parse(value, 16).map_err(|_| ProcError::parse(path, line, field))
+}
+
+// Addresses are printed as the hex of the in-memory words, so every 32 bit
+// word is in host byte order.
+fn parse_address(path: &str, line: usize, field: &str, value: &str)
+    -> Result<(String, u16), ProcError> {
+    let mut address_port = value.splitn(2, ':');
+    let (address, port) = match (address_port.next(), address_port.next()) {
+        (Some(address), Some(port)) => (address, port),
+        _ => return Err(ProcError::parse(path, line, field)),
+    };
+    let port = try!(parse_hex(path, line, field, port, u16::from_str_radix));
+
+    let mut words = Vec::new();
+    for i in 0..address.len() / 8 {
+        let word = try!(parse_hex(path, line, field, &address[i * 8..(i + 1) * 8], u32::from_str_radix));
+        words.push(u32::from_be(word));
+    }
+
+    let address = match address.len() {
+        8 => {
+            let a = words[0];
+            format!("{}", Ipv4Addr::new((a >> 24) as u8, (a >> 16) as u8, (a >> 8) as u8, a as u8))
+        },
+        32 => {
+            let s = words.iter().flat_map(|w| vec![(w >> 16) as u16, *w as u16]).collect::<Vec<u16>>();
+            format!("{}", Ipv6Addr::new(s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7]))
+        },
+        _ => return Err(ProcError::parse(path, line, field)),
+    };
+    Ok((address, port))
+}
+
+/// Parses a tcp, tcp6, udp or udp6 table.
+pub fn parse_inet_sockets(path: &str, protocol: Protocol, table: &str) -> Result<Vec<Socket>, ProcError> {
+    let mut sockets = Vec::new();
+
+    for (i, line) in table.lines().enumerate().skip(1) {
+        let n = i + 1;
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() < 10 {
+            return Err(ProcError::UnexpectedFormat(
+                format!("{}: {} fields on line {}", path, fields.len(), n)))
+        }
+
+        let (local_address, local_port) = try!(parse_address(path, n, "local_address", fields[1]));
+        let (remote_address, remote_port) = try!(parse_address(path, n, "rem_address", fields[2]));
+        let state = try!(parse_hex(path, n, "st", fields[3], u8::from_str_radix));
+        let mut queues = fields[4].splitn(2, ':');
+        let (tx_queue, rx_queue) = match (queues.next(), queues.next()) {
+            (Some(tx), Some(rx)) => (try!(parse_hex(path, n, "tx_queue", tx, usize::from_str_radix)),
+                                     try!(parse_hex(path, n, "rx_queue", rx, usize::from_str_radix))),
+            _ => return Err(ProcError::parse(path, n, "tx_queue")),
+        };
+        let mut timer = fields[5].splitn(2, ':');
+        let (timer, timer_expires) = match (timer.next(), timer.next()) {
+            (Some(tr), Some(when)) => (try!(parse_hex(path, n, "tr", tr, u8::from_str_radix)),
+                                       try!(parse_hex(path, n, "tm->when", when, usize::from_str_radix))),
+            _ => return Err(ProcError::parse(path, n, "tr")),
+        };
+
+        sockets.push(Socket {
+            protocol: protocol,
+            local_address: local_address,
+            local_port: local_port,
+            remote_address: remote_address,
+            remote_port: remote_port,
+            path: None,
+            state: SocketState::from_code(state),
+            tx_queue: tx_queue,
+            rx_queue: rx_queue,
+            timer: timer,
+            timer_expires: timer_expires,
+            retransmits: try!(parse_hex(path, n, "retrnsmt", fields[6], usize::from_str_radix)),
+            uid: Some(try!(parse_field(path, n, "uid", fields[7]))),
+            inode: try!(parse_field(path, n, "inode", fields[9])),
+        });
+    }
+
+    Ok(sockets)
+}
+
+// __SO_ACCEPTCON in the flags column marks a listening unix socket.
+const SO_ACCEPTCON: usize = 0x10000;
+// Unix socket states from include/uapi/linux/net.h.
+const SS_CONNECTING: u8 = 2;
+const SS_CONNECTED: u8 = 3;
+
+pub fn parse_unix_sockets(path: &str, table: &str) -> Result<Vec<Socket>, ProcError> {
+    let mut sockets = Vec::new();
+
+    for (i, line) in table.lines().enumerate().skip(1) {
+        let n = i + 1;
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() < 7 {
+            return Err(ProcError::UnexpectedFormat(
+                format!("{}: {} fields on line {}", path, fields.len(), n)))
+        }
+
+        let flags = try!(parse_hex(path, n, "Flags", fields[3], usize::from_str_radix));
+        let state = match try!(parse_hex(path, n, "St", fields[5], u8::from_str_radix)) {
+            _ if flags & SO_ACCEPTCON != 0 => SocketState::Listen,
+            SS_CONNECTING => SocketState::SynSent,
+            SS_CONNECTED => SocketState::Established,
+            _ => SocketState::Close,
+        };
+
+        sockets.push(Socket {
+            protocol: Protocol::Unix,
+            local_address: String::new(),
+            local_port: 0,
+            remote_address: String::new(),
+            remote_port: 0,
+            path: fields.get(7).map(|p| String::from(*p)),
+            state: state,
+            tx_queue: 0,
+            rx_queue: 0,
+            timer: 0,
+            timer_expires: 0,
+            retransmits: 0,
+            uid: None,
+            inode: try!(parse_field(path, n, "Inode", fields[6])),
+        });
+    }
+
+    Ok(sockets)
+}
+
+// "socket:[1234]" -> 1234
+fn socket_inode(target: &str) -> Option<u64> {
+    target.trim_left_matches("socket:[").trim_right_matches(']').parse().ok()
+}
+
+impl ProcFs {
+    // Tables for protocols the kernel lacks, e.g. tcp6 without IPv6, are
+    // treated as empty.
+    fn socket_table(&self, net_dir: &str, protocol: Protocol) -> Result<Vec<Socket>, ProcError> {
+        let path = self.path(&format!("{}/{}", net_dir, protocol.file()));
+        let table = match read_proc_file(&path) {
+            Ok(table) => table,
+            Err(ProcError::NotFound(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        match protocol {
+            Protocol::Unix => parse_unix_sockets(&path, &table),
+            _ => parse_inet_sockets(&path, protocol, &table),
+        }
+    }
+
+    fn socket_tables(&self, net_dir: &str) -> Result<Vec<Socket>, ProcError> {
+        let mut sockets = Vec::new();
+        for protocol in PROTOCOLS.iter() {
+            sockets.extend(try!(self.socket_table(net_dir, *protocol)));
+        }
+        Ok(sockets)
+    }
+
+    /// The sockets of a single protocol in our network namespace.
+    pub fn protocol_sockets(&self, protocol: Protocol) -> Result<Vec<Socket>, ProcError> {
+        self.socket_table("net", protocol)
+    }
+
+    /// Every socket in our network namespace.
+    pub fn sockets(&self) -> Result<Vec<Socket>, ProcError> {
+        self.socket_tables("net")
+    }
+
+    /// The sockets the process has open, looked up in the tables of its own
+    /// network namespace.
+    pub fn process_sockets(&self, pid: Pid) -> Result<Vec<ProcessSocket>, ProcError> {
+        let fds = try!(self.process_fds(pid));
+        let mut inodes = BTreeMap::new();
+        for fd in fds.iter().filter(|fd| fd.kind == FdKind::Socket) {
+            if let Some(inode) = socket_inode(&fd.target) {
+                inodes.insert(inode, fd.fd);
+            }
+        }
+
+        let sockets = try!(self.socket_tables(&format!("{}/net", pid)));
+        let mut result = sockets.into_iter()
+            .filter_map(|socket| inodes.get(&socket.inode).map(|fd| ProcessSocket {
+                fd: *fd,
+                socket: socket,
+            }))
+            .collect::<Vec<ProcessSocket>>();
+        result.sort_by(|a, b| a.fd.cmp(&b.fd));
+        Ok(result)
+    }
+
+    /// Sockets in our network namespace matching `filter`, each with the
+    /// processes holding it, like `ss -p` or `netstat -p`. Processes whose
+    /// descriptors we may not read are left out.
+    pub fn connections(&self, filter: &SocketFilter) -> Result<Vec<Connection>, ProcError> {
+        let mut owners: BTreeMap<u64, Vec<SocketOwner>> = BTreeMap::new();
+        for pid in try!(self.pids()) {
+            let fds = match self.process_fds(Pid::Id(pid)) {
+                Ok(fds) => fds,
+                Err(ProcError::NotFound(_)) | Err(ProcError::PermissionDenied(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            let comm = self.process_comm(Pid::Id(pid)).unwrap_or(String::new());
+            for fd in fds.iter().filter(|fd| fd.kind == FdKind::Socket) {
+                if let Some(inode) = socket_inode(&fd.target) {
+                    owners.entry(inode).or_insert_with(Vec::new).push(SocketOwner {
+                        pid: pid,
+                        comm: comm.clone(),
+                        fd: fd.fd,
+                    });
+                }
+            }
+        }
+
+        let sockets = try!(self.sockets());
+        Ok(sockets.into_iter()
+           .filter(|socket| filter.matches(socket))
+           .map(|socket| {
+               let owners = if socket.inode == 0 {
+                   Vec::new()
+               } else {
+                   owners.get(&socket.inode).cloned().unwrap_or(Vec::new())
+               };
+               Connection {
+                   socket: socket,
+                   owners: owners,
+               }
+           })
+           .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use proc_fs::sockets::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_sockets() {
+        assert!(ProcFs::default().sockets().is_ok());
+        assert!(ProcFs::default().process_sockets(Pid::Current).is_ok());
+    }
+
+    #[test]
+    fn test_sockets_fixture() {
+        let sockets = fixtures().sockets().unwrap();
+        assert_eq!(sockets.len(), 12);
+
+        let listen = &sockets[0];
+        assert_eq!(listen.protocol, Protocol::Tcp);
+        assert_eq!(listen.local_address, "0.0.0.0");
+        assert_eq!(listen.local_port, 8080);
+        assert_eq!(listen.state, SocketState::Listen);
+        assert_eq!(listen.tx_queue, 0);
+        assert_eq!(listen.rx_queue, 3);
+        assert_eq!(listen.uid, Some(1000));
+        assert_eq!(listen.inode, 20011);
+
+        let established = &sockets[1];
+        assert_eq!(established.local_address, "10.0.0.5");
+        assert_eq!(established.remote_address, "10.0.0.9");
+        assert_eq!(established.remote_port, 51298);
+        assert_eq!(established.state, SocketState::Established);
+        assert_eq!(established.timer, 2);
+        assert_eq!(established.timer_expires, 720000);
+
+        assert_eq!(sockets[2].state, SocketState::TimeWait);
+        assert_eq!(sockets[2].inode, 0);
+        assert_eq!(sockets[3].retransmits, 3);
+        assert_eq!(sockets[3].remote_address, "192.168.1.1");
+
+        let tcp6 = &sockets[4];
+        assert_eq!(tcp6.protocol, Protocol::Tcp6);
+        assert_eq!(tcp6.local_address, "::");
+        assert_eq!(tcp6.local_port, 22);
+        assert_eq!(sockets[5].local_address, "::ffff:10.0.0.5");
+
+        let udp = &sockets[7];
+        assert_eq!(udp.protocol, Protocol::Udp);
+        assert_eq!(udp.local_address, "127.0.0.1");
+        assert_eq!(udp.local_port, 8472);
+        assert_eq!(udp.state, SocketState::Close);
+        assert_eq!(udp.rx_queue, 2560);
+
+        let unix = &sockets[8];
+        assert_eq!(unix.protocol, Protocol::Unix);
+        assert_eq!(unix.path, Some(String::from("/run/supervisor.sock")));
+        assert_eq!(unix.state, SocketState::Listen);
+        assert_eq!(sockets[9].state, SocketState::Established);
+        assert_eq!(sockets[10].path, None);
+        assert_eq!(sockets[11].path, Some(String::from("@/org/kernel/udev/udevd")));
+        assert_eq!(sockets[11].state, SocketState::Close);
+    }
+
+    #[test]
+    fn test_process_sockets_fixture() {
+        let sockets = fixtures().process_sockets(Pid::Id(413)).unwrap();
+        let fds = sockets.iter().map(|s| (s.fd, s.socket.inode)).collect::<Vec<(usize, u64)>>();
+        assert_eq!(fds, vec![(2, 20011), (3, 20012)]);
+
+        let sockets = fixtures().process_sockets(Pid::Id(412)).unwrap();
+        assert_eq!(sockets[0].socket.path, Some(String::from("/run/supervisor.sock")));
+        assert!(fixtures().process_sockets(Pid::Id(414)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_connections_fixture() {
+        let all = fixtures().connections(&SocketFilter::default()).unwrap();
+        assert_eq!(all.len(), 12);
+        assert_eq!(all[0].owners, vec![SocketOwner { pid: 413, comm: String::from("worker"), fd: 2 }]);
+        assert_eq!(all[3].owners[0].pid, 1);
+        assert!(all[2].owners.is_empty());
+
+        let filter = SocketFilter {
+            protocol: None,
+            state: Some(SocketState::Listen),
+            port: Some(8080),
+        };
+        let listening = fixtures().connections(&filter).unwrap();
+        assert_eq!(listening.len(), 1);
+        assert_eq!(listening[0].socket.inode, 20011);
+
+        let filter = SocketFilter { protocol: Some(Protocol::Tcp), state: None, port: Some(8080) };
+        assert_eq!(fixtures().connections(&filter).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_parse_socket_filters() {
+        assert_eq!(SocketState::from_str("time-wait"), Ok(SocketState::TimeWait));
+        assert_eq!(SocketState::from_str("listen"), Ok(SocketState::Listen));
+        assert!(SocketState::from_str("LISTEN").is_err());
+        assert_eq!(Protocol::from_str("udp6"), Ok(Protocol::Udp6));
+        assert!(Protocol::from_str("sctp").is_err());
+    }
+
+    #[test]
+    fn test_parse_inet_sockets_bad_address() {
+        let table = "header\n   0: 0100007F 00000000:0000 0A 00000000:00000000 00:00000000 00000000 0 0 1\n";
+        assert!(parse_inet_sockets("tcp", Protocol::Tcp, table).is_err());
+        let table = "header\n   0: 0100007:0050 00000000:0000 0A 00000000:00000000 00:00000000 00000000 0 0 1\n";
+        assert!(parse_inet_sockets("tcp", Protocol::Tcp, table).is_err());
+    }
+}
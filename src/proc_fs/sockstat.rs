@@ -0,0 +1,245 @@
+/*
+ * Socket usage from /proc/net/sockstat and /proc/net/sockstat6, and how
+ * full the accept queues of listening TCP sockets are.
+ */
+use std::collections::BTreeMap;
+
+use proc_fs::{ProcFs, ProcError, read_proc_file, parse_field, page_size};
+use proc_fs::sockets::{Socket, SocketState, Protocol};
+
+// Socket counts of our network namespace. Memory is converted from pages to
+// bytes.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct SockStat {
+    pub sockets_used: usize, // sockets of every family
+    pub tcp_inuse: usize,
+    pub tcp_orphan: usize, // no longer attached to a file
+    pub tcp_tw: usize, // in time wait
+    pub tcp_alloc: usize,
+    pub tcp_mem: usize,
+    pub udp_inuse: usize,
+    pub udp_mem: usize,
+    pub udplite_inuse: usize,
+    pub raw_inuse: usize,
+    pub frag_inuse: usize,
+    pub frag_memory: usize, // already in bytes
+    pub ipv6: Option<SockStat6>, // None without IPv6
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct SockStat6 {
+    pub tcp6_inuse: usize,
+    pub udp6_inuse: usize,
+    pub udplite6_inuse: usize,
+    pub raw6_inuse: usize,
+    pub frag6_inuse: usize,
+    pub frag6_memory: usize,
+}
+
+// The accept queue of a listening socket. The kernel drops new connections,
+// counted in TcpExt ListenOverflows, once more than the backlog given to
+// listen(2) are queued. /proc/net/tcp does not show that backlog, only
+// sock_diag does, so the queue is measured against somaxconn, which caps it.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ListenQueue {
+    pub protocol: Protocol,
+    pub local_address: String,
+    pub local_port: u16,
+    pub inode: u64,
+    pub queued: usize, // connections waiting for accept(2)
+    pub max_backlog: usize, // net.core.somaxconn, an upper bound on the real backlog
+    pub saturation_percent: f64, // queued as a percentage of max_backlog, so a lower bound
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SocketSummary {
+    pub sockstat: SockStat,
+    pub listen_queues: Vec<ListenQueue>,
+}
+
+// Every line is "NAME: key value key value...".
+fn parse_sections(path: &str, contents: &str)
+    -> Result<BTreeMap<String, BTreeMap<String, usize>>, ProcError> {
+    let mut sections = BTreeMap::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let mut name_rest = line.splitn(2, ':');
+        let (name, rest) = match (name_rest.next(), name_rest.next()) {
+            (Some(name), Some(rest)) => (name, rest),
+            _ => continue,
+        };
+
+        let fields = rest.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() % 2 != 0 {
+            return Err(ProcError::parse(path, i + 1, name))
+        }
+        let mut values = BTreeMap::new();
+        for pair in fields.chunks(2) {
+            values.insert(String::from(pair[0]), try!(parse_field(path, i + 1, pair[0], pair[1])));
+        }
+        sections.insert(String::from(name), values);
+    }
+
+    Ok(sections)
+}
+
+// Keys missing on older kernels read as 0.
+fn value(sections: &BTreeMap<String, BTreeMap<String, usize>>, section: &str, key: &str) -> usize {
+    sections.get(section).and_then(|s| s.get(key)).map_or(0, |v| *v)
+}
+
+pub fn parse_sockstat(path: &str, sockstat: &str) -> Result<SockStat, ProcError> {
+    let sections = try!(parse_sections(path, sockstat));
+    if !sections.contains_key("sockets") {
+        return Err(ProcError::UnexpectedFormat(format!("{}: no sockets line", path)))
+    }
+    let pages = page_size();
+
+    Ok(SockStat {
+        sockets_used: value(&sections, "sockets", "used"),
+        tcp_inuse: value(&sections, "TCP", "inuse"),
+        tcp_orphan: value(&sections, "TCP", "orphan"),
+        tcp_tw: value(&sections, "TCP", "tw"),
+        tcp_alloc: value(&sections, "TCP", "alloc"),
+        tcp_mem: value(&sections, "TCP", "mem") * pages,
+        udp_inuse: value(&sections, "UDP", "inuse"),
+        udp_mem: value(&sections, "UDP", "mem") * pages,
+        udplite_inuse: value(&sections, "UDPLITE", "inuse"),
+        raw_inuse: value(&sections, "RAW", "inuse"),
+        frag_inuse: value(&sections, "FRAG", "inuse"),
+        frag_memory: value(&sections, "FRAG", "memory"),
+        ipv6: None,
+    })
+}
+
+pub fn parse_sockstat6(path: &str, sockstat6: &str) -> Result<SockStat6, ProcError> {
+    let sections = try!(parse_sections(path, sockstat6));
+
+    Ok(SockStat6 {
+        tcp6_inuse: value(&sections, "TCP6", "inuse"),
+        udp6_inuse: value(&sections, "UDP6", "inuse"),
+        udplite6_inuse: value(&sections, "UDPLITE6", "inuse"),
+        raw6_inuse: value(&sections, "RAW6", "inuse"),
+        frag6_inuse: value(&sections, "FRAG6", "inuse"),
+        frag6_memory: value(&sections, "FRAG6", "memory"),
+    })
+}
+
+/// For listening sockets the rx_queue column holds the accept queue length.
+/// Their tx_queue is always 0, so the length is reported against
+/// `max_backlog`, the somaxconn the listen(2) backlog is capped at.
+pub fn listen_queues(sockets: &[Socket], max_backlog: usize) -> Vec<ListenQueue> {
+    sockets.iter()
+        .filter(|s| s.state == SocketState::Listen &&
+                (s.protocol == Protocol::Tcp || s.protocol == Protocol::Tcp6))
+        .map(|s| ListenQueue {
+            protocol: s.protocol,
+            local_address: s.local_address.clone(),
+            local_port: s.local_port,
+            inode: s.inode,
+            queued: s.rx_queue,
+            max_backlog: max_backlog,
+            saturation_percent: if max_backlog == 0 {
+                if s.rx_queue == 0 { 0.0 } else { 100.0 }
+            } else {
+                100.0 * s.rx_queue as f64 / max_backlog as f64
+            },
+        })
+        .collect()
+}
+
+impl ProcFs {
+    pub fn sockstat(&self) -> Result<SockStat, ProcError> {
+        let path = self.path("net/sockstat");
+        let sockstat = try!(read_proc_file(&path));
+        let mut stat = try!(parse_sockstat(&path, &sockstat));
+
+        let path = self.path("net/sockstat6");
+        stat.ipv6 = match read_proc_file(&path) {
+            Ok(sockstat6) => Some(try!(parse_sockstat6(&path, &sockstat6))),
+            Err(ProcError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(stat)
+    }
+
+    /// net.core.somaxconn of our network namespace, the largest backlog
+    /// listen(2) accepts.
+    pub fn somaxconn(&self) -> Result<usize, ProcError> {
+        let path = self.path("sys/net/core/somaxconn");
+        let somaxconn = try!(read_proc_file(&path));
+        parse_field(&path, 1, "somaxconn", somaxconn.trim())
+    }
+
+    /// Accept queues of every listening TCP socket in our network namespace.
+    pub fn listen_queues(&self) -> Result<Vec<ListenQueue>, ProcError> {
+        let mut sockets = try!(self.protocol_sockets(Protocol::Tcp));
+        sockets.extend(try!(self.protocol_sockets(Protocol::Tcp6)));
+        Ok(listen_queues(&sockets, try!(self.somaxconn())))
+    }
+
+    pub fn socket_summary(&self) -> Result<SocketSummary, ProcError> {
+        Ok(SocketSummary {
+            sockstat: try!(self.sockstat()),
+            listen_queues: try!(self.listen_queues()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::sockstat::*;
+    use proc_fs::sockets::Protocol;
+    use proc_fs::{ProcFs, fixtures, page_size};
+
+    #[test]
+    fn test_socket_summary() {
+        let summary = ProcFs::default().socket_summary().unwrap();
+        assert!(summary.sockstat.sockets_used > 0);
+    }
+
+    #[test]
+    fn test_sockstat_fixture() {
+        let stat = fixtures().sockstat().unwrap();
+        assert_eq!(stat.sockets_used, 1043);
+        assert_eq!(stat.tcp_inuse, 212);
+        assert_eq!(stat.tcp_orphan, 3);
+        assert_eq!(stat.tcp_tw, 1880);
+        assert_eq!(stat.tcp_alloc, 240);
+        assert_eq!(stat.tcp_mem, 57 * page_size());
+        assert_eq!(stat.udp_mem, 4 * page_size());
+        assert_eq!(stat.raw_inuse, 1);
+
+        let ipv6 = stat.ipv6.unwrap();
+        assert_eq!(ipv6.tcp6_inuse, 18);
+        assert_eq!(ipv6.udp6_inuse, 2);
+    }
+
+    #[test]
+    fn test_parse_sockstat() {
+        let stat = parse_sockstat("sockstat", "sockets: used 3\nTCP: inuse 1\n").unwrap();
+        assert_eq!(stat.tcp_inuse, 1);
+        assert_eq!(stat.tcp_tw, 0);
+
+        assert!(parse_sockstat("sockstat", "TCP: inuse 1\n").is_err());
+        assert!(parse_sockstat("sockstat", "sockets: used\n").is_err());
+        assert!(parse_sockstat("sockstat", "sockets: used x\n").is_err());
+    }
+
+    #[test]
+    fn test_listen_queues_fixture() {
+        let queues = fixtures().listen_queues().unwrap();
+        assert_eq!(queues.len(), 2);
+
+        assert_eq!(queues[0].protocol, Protocol::Tcp);
+        assert_eq!(queues[0].local_port, 8080);
+        assert_eq!(queues[0].inode, 20011);
+        assert_eq!(queues[0].queued, 3);
+        assert_eq!(queues[0].max_backlog, 4096);
+        assert_eq!(queues[0].saturation_percent, 0.0732421875);
+
+        assert_eq!(queues[1].protocol, Protocol::Tcp6);
+        assert_eq!(queues[1].local_port, 22);
+        assert_eq!(queues[1].saturation_percent, 0.0);
+    }
+}
@@ -1,87 +1,207 @@
 /*
  * Get a process's status from /proc/<pid>/stat
  */
-use std::fs::File;
 use std::string::String;
 use std::str::FromStr;
-use std::io::{self, Read};
-
-#[derive(Debug)]
-#[allow(dead_code)]
-struct ProcStat {
-    pid: isize,
-    command: String,
-    state: char,
-    ppid: isize, // PID of the parent
-    gprp: isize, // Process group id of process
-    session: isize,
-    tty_nr: isize, // Controlling terminal
-    tpgid: isize, // id of foreground process group of controlling terminal
-    flags: usize, // Kernel flags word. See PF_* defines
-    minfly: usize, // # of minor faults
-    cminflt: usize, // # of minor faults children have made
-    majflt: usize, // # of major faults process has made
-    cmajflt: usize, // # of major faults children have made
-    utime: usize, // Amt of time process has been scheduled in usermode
-    stime: usize, // Amt of time process has been scheduled in kernel mode
-    cutime: isize, // Amt of time children have been scheduled in usermode
-    cstime: isize, // Amt of time children has been scheduled in kernel mode
-    priority: isize, // scheduling priority
-    nice: isize, // nice value
-    num_threads: isize,
-    itrealval: isize, // time in jiffies before next SIGALRM
-    starttime: usize, // Time started after system boot
-    vsize: usize, // virtual memory size in bytes
-    rss: isize, // Resident Set Size: # of pages in real memory
-    rsslim: usize, // soft limit in bytes on rss of process
-    startcode: usize, // Address above which program text can run
-    endcode: usize,  // Address below which program text can run
-    startstack: usize, // Address of start of stack
-    kstkesp: usize, // current EIP (instruction pointer)
-    signal: usize, // bitmap of pending signals (Obsolete)
-    blocked: usize, // bitmap of blocked signals (Obsolete)
-    sigignore: usize, // bitmap of ignored signals (Obsolete)
-    sigcatch: usize, // bitmap of caught signals (Obsolete)
-    wchan: usize, // "wait channel", address in kernel where process is sleeping
-    nswap: usize, // # of pages swapped (not maintained)
-    cnswap: usize, // # of pages swapped for child processes (not maintained)
-    exit_signal: isize, // Signal to be sent to parent when we die
-    processor: usize, // CPU number last executed on
-    rt_priority: usize, // real-time scheduling priority (1-99) or 0
-    policy: usize, // Scheduling policy, SCHED_* constants
-    delayacct_blkio_ticks: usize, // Aggregated block i/o delays in clock ticks
-    guest_time: usize, // Guest time of process (time on virtual CPU) in clock ticks
-    cguest_time: isize, // Guest time of children (time on virtual CPU) in clock ticks
-    start_data: usize, // Address above which program {un,}initialized data is placed
-    end_data: usize, // Address below which program {un,}initialized data is placed
-    start_brk: usize, // Address above which program can be expanded
-    arg_start: usize, // Address above which program command-line args are placed
-    arg_end: usize, // Address below which program command-line args are placed
-    env_start: usize, // Address above which program environment are placed
-    env_end: usize, // Address below which program environment are placed
-    exit_code: usize, // thread's exit status
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field};
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProcStat {
+    pub pid: isize,
+    pub command: String,
+    pub state: char,
+    pub ppid: isize, // PID of the parent
+    pub pgrp: isize, // Process group id of process
+    pub session: isize,
+    pub tty_nr: isize, // Controlling terminal
+    pub tpgid: isize, // id of foreground process group of controlling terminal
+    pub flags: usize, // Kernel flags word. See PF_* defines
+    pub minflt: usize, // # of minor faults
+    pub cminflt: usize, // # of minor faults children have made
+    pub majflt: usize, // # of major faults process has made
+    pub cmajflt: usize, // # of major faults children have made
+    pub utime: usize, // Amt of time process has been scheduled in usermode
+    pub stime: usize, // Amt of time process has been scheduled in kernel mode
+    pub cutime: isize, // Amt of time children have been scheduled in usermode
+    pub cstime: isize, // Amt of time children has been scheduled in kernel mode
+    pub priority: isize, // scheduling priority
+    pub nice: isize, // nice value
+    pub num_threads: isize,
+    pub itrealvalue: isize, // time in jiffies before next SIGALRM
+    pub starttime: usize, // Time started after system boot
+    pub vsize: usize, // virtual memory size in bytes
+    pub rss: isize, // Resident Set Size: # of pages in real memory
+    pub rsslim: usize, // soft limit in bytes on rss of process
+    pub startcode: usize, // Address above which program text can run
+    pub endcode: usize,  // Address below which program text can run
+    pub startstack: usize, // Address of start of stack
+    pub kstkesp: usize, // current ESP (stack pointer)
+    pub kstkeip: usize, // current EIP (instruction pointer)
+    pub signal: usize, // bitmap of pending signals (Obsolete)
+    pub blocked: usize, // bitmap of blocked signals (Obsolete)
+    pub sigignore: usize, // bitmap of ignored signals (Obsolete)
+    pub sigcatch: usize, // bitmap of caught signals (Obsolete)
+    pub wchan: usize, // "wait channel", address in kernel where process is sleeping
+    pub nswap: usize, // # of pages swapped (not maintained)
+    pub cnswap: usize, // # of pages swapped for child processes (not maintained)
+    pub exit_signal: isize, // Signal to be sent to parent when we die
+    pub processor: usize, // CPU number last executed on
+    pub rt_priority: usize, // real-time scheduling priority (1-99) or 0
+    pub policy: usize, // Scheduling policy, SCHED_* constants
+    pub delayacct_blkio_ticks: usize, // Aggregated block i/o delays in clock ticks
+    pub guest_time: usize, // Guest time of process (time on virtual CPU) in clock ticks
+    pub cguest_time: isize, // Guest time of children (time on virtual CPU) in clock ticks
+    pub start_data: usize, // Address above which program {un,}initialized data is placed
+    pub end_data: usize, // Address below which program {un,}initialized data is placed
+    pub start_brk: usize, // Address above which program can be expanded
+    pub arg_start: usize, // Address above which program command-line args are placed
+    pub arg_end: usize, // Address below which program command-line args are placed
+    pub env_start: usize, // Address above which program environment are placed
+    pub env_end: usize, // Address below which program environment are placed
+    pub exit_code: usize, // thread's exit status
+}
+
+impl ProcFs {
+    pub fn process_stat(&self, pid: Pid) -> Result<ProcStat, ProcError> {
+        let path = self.path(&format!("{}/stat", pid));
+        let stat_str = try!(read_proc_file(&path));
+
+        parse_stat(&path, &stat_str)
+    }
+
+    pub fn process_statm(&self, pid: Pid) -> Result<ProcStatm, ProcError> {
+        let path = self.path(&format!("{}/statm", pid));
+        let stats_str = try!(read_proc_file(&path));
+
+        parse_statm(&path, &stats_str)
+    }
+
+    pub fn process_io(&self, pid: Pid) -> Result<ProcIo, ProcError> {
+        let path = self.path(&format!("{}/io", pid));
+        let stats_str = try!(read_proc_file(&path));
+
+        parse_io(&path, &stats_str)
+    }
+}
+
+fn stat_field<'a, I, T>(file: &str, fields: &mut I, name: &str) -> Result<T, ProcError>
+    where I: Iterator<Item=&'a str>, T: FromStr {
+    match fields.next() {
+        Some(s) => parse_field(file, 1, name, s),
+        None => Err(ProcError::parse(file, 1, name)),
+    }
+}
+
+// Fields from start_data onwards were added in later kernels (3.3, 3.5), so
+// they are allowed to be missing.
+fn optional_stat_field<'a, I, T>(file: &str, fields: &mut I, name: &str) -> Result<T, ProcError>
+    where I: Iterator<Item=&'a str>, T: FromStr + Default {
+    match fields.next() {
+        Some(s) => parse_field(file, 1, name, s),
+        None => Ok(T::default()),
+    }
+}
+
+// The command is wrapped in parentheses and may itself contain spaces and
+// parentheses, so it spans from the first '(' to the last ')'.
+pub fn parse_stat(file: &str, stat_str: &str) -> Result<ProcStat, ProcError> {
+    let open = try!(stat_str.find('(').ok_or(ProcError::parse(file, 1, "command")));
+    let close = try!(stat_str.rfind(')').ok_or(ProcError::parse(file, 1, "command")));
+    if close < open {
+        return Err(ProcError::parse(file, 1, "command"))
+    }
+
+    let pid = try!(parse_field(file, 1, "pid", stat_str[..open].trim()));
+    let command = String::from(&stat_str[open + 1..close]);
+
+    let mut fields = stat_str[close + 1..].split_whitespace();
+    let state = match fields.next().and_then(|s| s.chars().next()) {
+        Some(c) => c,
+        None => return Err(ProcError::parse(file, 1, "state")),
+    };
+    let f = &mut fields;
+
+    Ok(ProcStat {
+        pid: pid,
+        command: command,
+        state: state,
+        ppid: try!(stat_field(file, f, "ppid")),
+        pgrp: try!(stat_field(file, f, "pgrp")),
+        session: try!(stat_field(file, f, "session")),
+        tty_nr: try!(stat_field(file, f, "tty_nr")),
+        tpgid: try!(stat_field(file, f, "tpgid")),
+        flags: try!(stat_field(file, f, "flags")),
+        minflt: try!(stat_field(file, f, "minflt")),
+        cminflt: try!(stat_field(file, f, "cminflt")),
+        majflt: try!(stat_field(file, f, "majflt")),
+        cmajflt: try!(stat_field(file, f, "cmajflt")),
+        utime: try!(stat_field(file, f, "utime")),
+        stime: try!(stat_field(file, f, "stime")),
+        cutime: try!(stat_field(file, f, "cutime")),
+        cstime: try!(stat_field(file, f, "cstime")),
+        priority: try!(stat_field(file, f, "priority")),
+        nice: try!(stat_field(file, f, "nice")),
+        num_threads: try!(stat_field(file, f, "num_threads")),
+        itrealvalue: try!(stat_field(file, f, "itrealvalue")),
+        starttime: try!(stat_field(file, f, "starttime")),
+        vsize: try!(stat_field(file, f, "vsize")),
+        rss: try!(stat_field(file, f, "rss")),
+        rsslim: try!(stat_field(file, f, "rsslim")),
+        startcode: try!(stat_field(file, f, "startcode")),
+        endcode: try!(stat_field(file, f, "endcode")),
+        startstack: try!(stat_field(file, f, "startstack")),
+        kstkesp: try!(stat_field(file, f, "kstkesp")),
+        kstkeip: try!(stat_field(file, f, "kstkeip")),
+        signal: try!(stat_field(file, f, "signal")),
+        blocked: try!(stat_field(file, f, "blocked")),
+        sigignore: try!(stat_field(file, f, "sigignore")),
+        sigcatch: try!(stat_field(file, f, "sigcatch")),
+        wchan: try!(stat_field(file, f, "wchan")),
+        nswap: try!(stat_field(file, f, "nswap")),
+        cnswap: try!(stat_field(file, f, "cnswap")),
+        exit_signal: try!(stat_field(file, f, "exit_signal")),
+        processor: try!(stat_field(file, f, "processor")),
+        rt_priority: try!(stat_field(file, f, "rt_priority")),
+        policy: try!(stat_field(file, f, "policy")),
+        delayacct_blkio_ticks: try!(stat_field(file, f, "delayacct_blkio_ticks")),
+        guest_time: try!(stat_field(file, f, "guest_time")),
+        cguest_time: try!(stat_field(file, f, "cguest_time")),
+        start_data: try!(optional_stat_field(file, f, "start_data")),
+        end_data: try!(optional_stat_field(file, f, "end_data")),
+        start_brk: try!(optional_stat_field(file, f, "start_brk")),
+        arg_start: try!(optional_stat_field(file, f, "arg_start")),
+        arg_end: try!(optional_stat_field(file, f, "arg_end")),
+        env_start: try!(optional_stat_field(file, f, "env_start")),
+        env_end: try!(optional_stat_field(file, f, "env_end")),
+        exit_code: try!(optional_stat_field(file, f, "exit_code")),
+    })
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ProcStatm {
-    size: usize, // total program size
-    resident: usize, // resident set size
-    share: usize, // shared pages (i.e. backed by file)
-    text: usize, // text (code)
-    lib: usize, // Library
-    data: usize, // Data + stack
-    dt: usize, // Dirty pages
+    pub size: usize, // total program size
+    pub resident: usize, // resident set size
+    pub share: usize, // shared pages (i.e. backed by file)
+    pub text: usize, // text (code)
+    pub lib: usize, // Library
+    pub data: usize, // Data + stack
+    pub dt: usize, // Dirty pages
 }
 
-pub fn process_statm(pid: String) -> io::Result<ProcStatm> {
-    let mut f = try!(File::open(format!("/proc/{}/statm", pid)));
-    let mut stats_str = String::new();
-    let _n = f.read_to_string(&mut stats_str).unwrap();
+const STATM_FIELDS: [&'static str; 7] = ["size", "resident", "share", "text", "lib", "data", "dt"];
 
-    let stats: Vec<usize> = stats_str.split_whitespace()
-        .map(|s| usize::from_str(s).unwrap())
-        .collect();
-    assert_eq!(stats.len(), 7);
+pub fn parse_statm(path: &str, stats_str: &str) -> Result<ProcStatm, ProcError> {
+    let values = stats_str.split_whitespace().collect::<Vec<&str>>();
+    if values.len() < STATM_FIELDS.len() {
+        return Err(ProcError::UnexpectedFormat(
+            format!("{}: expected {} fields, found {}", path, STATM_FIELDS.len(), values.len())))
+    }
+
+    let mut stats = [0; 7];
+    for (i, name) in STATM_FIELDS.iter().enumerate() {
+        stats[i] = try!(parse_field(path, 1, name, values[i]));
+    }
 
     Ok(ProcStatm {
         size: stats[0],
@@ -94,46 +214,101 @@ pub fn process_statm(pid: String) -> io::Result<ProcStatm> {
     })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ProcIo {
-    rchar: usize, // bytes passed to read syscalls
-    wchar: usize, // bytes passed to write syscalls
-    syscr: usize, // read syscall count
-    syscw: usize, // write syscall count
-    read_bytes: usize, // Count of bytes read from storage layer
-    write_bytes: usize, // Count of bytes sent to storage layer
-    cancelled_write_bytes: usize, // count of bytes the process caused to not be written
+    pub rchar: usize, // bytes passed to read syscalls
+    pub wchar: usize, // bytes passed to write syscalls
+    pub syscr: usize, // read syscall count
+    pub syscw: usize, // write syscall count
+    pub read_bytes: usize, // Count of bytes read from storage layer
+    pub write_bytes: usize, // Count of bytes sent to storage layer
+    pub cancelled_write_bytes: usize, // count of bytes the process caused to not be written
 }
 
-pub fn process_io(pid: String) -> io::Result<ProcIo> {
-    let mut f = try!(File::open(format!("/proc/{}/io", pid)));
-    let mut stats_str = String::new();
-    let _n = f.read_to_string(&mut stats_str).unwrap();
-
-    let stats: Vec<usize> = stats_str.split_whitespace()
-        .enumerate()
-        .filter(|&(i, _s)| i % 2 != 0)
-        .map(|(_i, s)| s)
-        .map(|s| usize::from_str(s).unwrap())
-        .collect();
-    assert_eq!(stats.len(), 7);
-
-    Ok(ProcIo {
-        rchar: stats[0],
-        wchar: stats[1],
-        syscr: stats[2],
-        syscw: stats[3],
-        read_bytes: stats[4],
-        write_bytes: stats[5],
-        cancelled_write_bytes: stats[6],
-    })
+pub fn parse_io(path: &str, stats_str: &str) -> Result<ProcIo, ProcError> {
+    let mut io = ProcIo {
+        rchar: 0,
+        wchar: 0,
+        syscr: 0,
+        syscw: 0,
+        read_bytes: 0,
+        write_bytes: 0,
+        cancelled_write_bytes: 0,
+    };
+    let mut found = 0;
+
+    for (i, line) in stats_str.lines().enumerate() {
+        let mut kv = line.splitn(2, ':');
+        let (key, value) = match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) => (k.trim(), v.trim()),
+            _ => continue,
+        };
+
+        let field = match key {
+            "rchar" => &mut io.rchar,
+            "wchar" => &mut io.wchar,
+            "syscr" => &mut io.syscr,
+            "syscw" => &mut io.syscw,
+            "read_bytes" => &mut io.read_bytes,
+            "write_bytes" => &mut io.write_bytes,
+            "cancelled_write_bytes" => &mut io.cancelled_write_bytes,
+            _ => continue, // Newer kernels may add keys we don't know about
+        };
+        *field = try!(parse_field(path, i + 1, key, value));
+        found += 1;
+    }
+
+    if found < 7 {
+        return Err(ProcError::UnexpectedFormat(
+            format!("{}: expected 7 known keys, found {}", path, found)))
+    }
+
+    Ok(io)
 }
 
 #[cfg(test)]
 mod test {
     use std::process::Command;
     use proc_fs::stats::*;
-    use proc_fs::ToPid;
+    use proc_fs::{ProcFs, Pid, ProcError, fixtures};
+
+    #[test]
+    fn test_proc_stat() {
+        let id = Command::new("sh")
+            .arg("-c")
+            .arg("sleep 1")
+            .spawn()
+            .unwrap_or_else(|e| { panic!("failed to execute process: {}", e) }).id();
+
+        let proc_fs = ProcFs::default();
+        let stats = proc_fs.process_stat(Pid::Id(id));
+        assert!(stats.is_ok());
+        assert_eq!(stats.unwrap().pid, id as isize);
+
+        let stats = proc_fs.process_stat(Pid::Current);
+        assert!(stats.is_ok());
+    }
+
+    #[test]
+    fn test_parse_stat_command() {
+        let line = "42 (my (odd) cmd) S 1 42 42 0 -1 4194560 100 0 0 0 7 3 0 0 20 0 1 0 \
+                    1234 1000000 200 18446744073709551615 1 1 0 0 0 0 0 4096 0 0 0 0 17 2 0 0 \
+                    5 0 0 0 0 0 0 0 0 0 0";
+        let stat = parse_stat("/proc/42/stat", line).unwrap();
+        assert_eq!(stat.pid, 42);
+        assert_eq!(stat.command, "my (odd) cmd");
+        assert_eq!(stat.state, 'S');
+        assert_eq!(stat.ppid, 1);
+        assert_eq!(stat.utime, 7);
+        assert_eq!(stat.stime, 3);
+        assert_eq!(stat.processor, 2);
+        assert_eq!(stat.delayacct_blkio_ticks, 5);
+
+        match parse_stat("/proc/42/stat", "42 (truncated) S 1 42") {
+            Err(ProcError::Parse { field, .. }) => assert_eq!(field, "tty_nr"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 
     #[test]
     fn test_proc_statm() {
@@ -143,10 +318,11 @@ mod test {
             .spawn()
             .unwrap_or_else(|e| { panic!("failed to execute process: {}", e) }).id();
 
-        let stats = process_statm(id.to_pid());
+        let proc_fs = ProcFs::default();
+        let stats = proc_fs.process_statm(Pid::Id(id));
         assert!(stats.is_ok());
 
-        let stats = process_statm("self".to_pid());
+        let stats = proc_fs.process_statm(Pid::Current);
         assert!(stats.is_ok());
     }
 
@@ -158,10 +334,31 @@ mod test {
             .spawn()
             .unwrap_or_else(|e| { panic!("failed to execute process: {}", e) }).id();
 
-        let stats = process_io(id.to_pid());
+        let proc_fs = ProcFs::default();
+        let stats = proc_fs.process_io(Pid::Id(id));
         assert!(stats.is_ok());
 
-        let stats = process_io("self".to_pid());
+        let stats = proc_fs.process_io(Pid::Current);
         assert!(stats.is_ok());
     }
+
+    #[test]
+    fn test_fixtures() {
+        let proc_fs = fixtures();
+
+        let stat = proc_fs.process_stat(Pid::Id(1)).unwrap();
+        assert_eq!(stat.command, "init");
+        assert_eq!(stat.num_threads, 1);
+
+        let statm = proc_fs.process_statm(Pid::Id(1)).unwrap();
+        assert_eq!(statm.resident, 1203);
+
+        let io = proc_fs.process_io(Pid::Id(1)).unwrap();
+        assert_eq!(io.read_bytes, 81215488);
+
+        match proc_fs.process_statm(Pid::Id(2)) {
+            Err(ProcError::NotFound(_)) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,227 @@
+/*
+ * Get a process's human readable status from /proc/<pid>/status
+ */
+use std::string::String;
+
+use proc_fs::{ProcFs, Pid, ProcError, read_proc_file, parse_field};
+
+/// Real, effective, saved set and filesystem ids from the Uid and Gid lines.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct IdSet {
+    pub real: usize,
+    pub effective: usize,
+    pub saved: usize,
+    pub filesystem: usize,
+}
+
+// Most keys come and go between kernel versions, so everything except the
+// name is optional. Memory sizes are converted from kB to bytes.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProcStatus {
+    pub name: String,
+    pub state: Option<char>,
+    pub tgid: Option<usize>, // Thread group id
+    pub ppid: Option<usize>, // PID of the parent
+    pub uid: Option<IdSet>,
+    pub gid: Option<IdSet>,
+    pub vm_peak: Option<usize>, // Peak virtual memory size
+    pub vm_size: Option<usize>, // Virtual memory size
+    pub vm_lck: Option<usize>, // Locked memory
+    pub vm_hwm: Option<usize>, // Peak resident set size ("high water mark")
+    pub vm_rss: Option<usize>, // Resident set size, RssAnon + RssFile + RssShmem
+    pub rss_anon: Option<usize>, // Resident anonymous memory
+    pub rss_file: Option<usize>, // Resident file mappings
+    pub rss_shmem: Option<usize>, // Resident shared memory
+    pub vm_swap: Option<usize>, // Swapped out anonymous memory
+    pub threads: Option<usize>,
+    pub voluntary_ctxt_switches: Option<usize>,
+    pub nonvoluntary_ctxt_switches: Option<usize>,
+    pub cpus_allowed_list: Option<String>, // e.g. "0-3,8"
+    pub sig_queued: Option<usize>, // Signals queued for the real user id
+    pub sig_queue_limit: Option<usize>, // Limit on queued signals
+    pub sig_pending_thread: Option<u64>, // SigPnd mask
+    pub sig_pending_process: Option<u64>, // ShdPnd mask
+    pub sig_blocked: Option<u64>, // SigBlk mask
+    pub sig_ignored: Option<u64>, // SigIgn mask
+    pub sig_caught: Option<u64>, // SigCgt mask
+}
+
+impl ProcFs {
+    pub fn process_status(&self, pid: Pid) -> Result<ProcStatus, ProcError> {
+        let path = self.path(&format!("{}/status", pid));
+        let status_str = try!(read_proc_file(&path));
+
+        parse_status(&path, &status_str)
+    }
+}
+
+fn parse_kb(path: &str, line: usize, key: &str, value: &str) -> Result<usize, ProcError> {
+    let kb: usize = try!(parse_field(path, line, key, value.trim_right_matches("kB").trim()));
+    Ok(kb * 1024)
+}
+
+fn parse_ids(path: &str, line: usize, key: &str, value: &str) -> Result<IdSet, ProcError> {
+    let ids = value.split_whitespace().collect::<Vec<&str>>();
+    if ids.len() != 4 {
+        return Err(ProcError::parse(path, line, key))
+    }
+
+    Ok(IdSet {
+        real: try!(parse_field(path, line, key, ids[0])),
+        effective: try!(parse_field(path, line, key, ids[1])),
+        saved: try!(parse_field(path, line, key, ids[2])),
+        filesystem: try!(parse_field(path, line, key, ids[3])),
+    })
+}
+
+fn parse_mask(path: &str, line: usize, key: &str, value: &str) -> Result<u64, ProcError> {
+    u64::from_str_radix(value, 16).map_err(|_| ProcError::parse(path, line, key))
+}
+
+pub fn parse_status(path: &str, status_str: &str) -> Result<ProcStatus, ProcError> {
+    let mut status = ProcStatus {
+        name: String::new(),
+        state: None,
+        tgid: None,
+        ppid: None,
+        uid: None,
+        gid: None,
+        vm_peak: None,
+        vm_size: None,
+        vm_lck: None,
+        vm_hwm: None,
+        vm_rss: None,
+        rss_anon: None,
+        rss_file: None,
+        rss_shmem: None,
+        vm_swap: None,
+        threads: None,
+        voluntary_ctxt_switches: None,
+        nonvoluntary_ctxt_switches: None,
+        cpus_allowed_list: None,
+        sig_queued: None,
+        sig_queue_limit: None,
+        sig_pending_thread: None,
+        sig_pending_process: None,
+        sig_blocked: None,
+        sig_ignored: None,
+        sig_caught: None,
+    };
+    let mut has_name = false;
+
+    for (i, line) in status_str.lines().enumerate() {
+        let n = i + 1;
+        let mut kv = line.splitn(2, ':');
+        let (key, value) = match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) => (k, v.trim()),
+            _ => continue,
+        };
+
+        match key {
+            "Name" => {
+                status.name = String::from(value);
+                has_name = true;
+            },
+            // e.g. "S (sleeping)"
+            "State" => status.state = value.chars().next(),
+            "Tgid" => status.tgid = Some(try!(parse_field(path, n, key, value))),
+            "PPid" => status.ppid = Some(try!(parse_field(path, n, key, value))),
+            "Uid" => status.uid = Some(try!(parse_ids(path, n, key, value))),
+            "Gid" => status.gid = Some(try!(parse_ids(path, n, key, value))),
+            "VmPeak" => status.vm_peak = Some(try!(parse_kb(path, n, key, value))),
+            "VmSize" => status.vm_size = Some(try!(parse_kb(path, n, key, value))),
+            "VmLck" => status.vm_lck = Some(try!(parse_kb(path, n, key, value))),
+            "VmHWM" => status.vm_hwm = Some(try!(parse_kb(path, n, key, value))),
+            "VmRSS" => status.vm_rss = Some(try!(parse_kb(path, n, key, value))),
+            "RssAnon" => status.rss_anon = Some(try!(parse_kb(path, n, key, value))),
+            "RssFile" => status.rss_file = Some(try!(parse_kb(path, n, key, value))),
+            "RssShmem" => status.rss_shmem = Some(try!(parse_kb(path, n, key, value))),
+            "VmSwap" => status.vm_swap = Some(try!(parse_kb(path, n, key, value))),
+            "Threads" => status.threads = Some(try!(parse_field(path, n, key, value))),
+            "voluntary_ctxt_switches" =>
+                status.voluntary_ctxt_switches = Some(try!(parse_field(path, n, key, value))),
+            "nonvoluntary_ctxt_switches" =>
+                status.nonvoluntary_ctxt_switches = Some(try!(parse_field(path, n, key, value))),
+            "Cpus_allowed_list" => status.cpus_allowed_list = Some(String::from(value)),
+            // e.g. "0/63707"
+            "SigQ" => {
+                let mut q = value.splitn(2, '/');
+                match (q.next(), q.next()) {
+                    (Some(queued), Some(limit)) => {
+                        status.sig_queued = Some(try!(parse_field(path, n, key, queued)));
+                        status.sig_queue_limit = Some(try!(parse_field(path, n, key, limit)));
+                    },
+                    _ => return Err(ProcError::parse(path, n, key)),
+                }
+            },
+            "SigPnd" => status.sig_pending_thread = Some(try!(parse_mask(path, n, key, value))),
+            "ShdPnd" => status.sig_pending_process = Some(try!(parse_mask(path, n, key, value))),
+            "SigBlk" => status.sig_blocked = Some(try!(parse_mask(path, n, key, value))),
+            "SigIgn" => status.sig_ignored = Some(try!(parse_mask(path, n, key, value))),
+            "SigCgt" => status.sig_caught = Some(try!(parse_mask(path, n, key, value))),
+            _ => {}, // Keys we don't care about or that this kernel doesn't know
+        }
+    }
+
+    if !has_name {
+        return Err(ProcError::UnexpectedFormat(format!("{}: no Name line", path)))
+    }
+
+    Ok(status)
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+    use proc_fs::status::*;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_proc_status() {
+        let id = Command::new("sh")
+            .arg("-c")
+            .arg("sleep 1")
+            .spawn()
+            .unwrap_or_else(|e| { panic!("failed to execute process: {}", e) }).id();
+
+        let proc_fs = ProcFs::default();
+        let status = proc_fs.process_status(Pid::Id(id));
+        assert!(status.is_ok());
+        assert_eq!(status.unwrap().tgid, Some(id as usize));
+
+        let status = proc_fs.process_status(Pid::Current);
+        assert!(status.is_ok());
+    }
+
+    #[test]
+    fn test_proc_status_fixture() {
+        let status = fixtures().process_status(Pid::Id(1)).unwrap();
+        assert_eq!(status.name, "init");
+        assert_eq!(status.state, Some('S'));
+        assert_eq!(status.ppid, Some(0));
+        assert_eq!(status.uid, Some(IdSet { real: 0, effective: 0, saved: 0, filesystem: 0 }));
+        assert_eq!(status.vm_hwm, Some(10612 * 1024));
+        assert_eq!(status.vm_rss, Some(10612 * 1024));
+        assert_eq!(status.rss_anon, Some(2764 * 1024));
+        assert_eq!(status.threads, Some(1));
+        assert_eq!(status.nonvoluntary_ctxt_switches, Some(1214));
+        assert_eq!(status.cpus_allowed_list, Some(String::from("0-3")));
+        assert_eq!(status.sig_queued, Some(0));
+        assert_eq!(status.sig_queue_limit, Some(63707));
+        assert_eq!(status.sig_blocked, Some(0x7be3c0fe28014a03));
+    }
+
+    #[test]
+    fn test_parse_status_old_kernel() {
+        // No Rss* breakdown, VmSwap or Cpus_allowed_list before 2.6.x/4.5
+        let status = parse_status("/proc/7/status", "Name:\tfoo\n\
+                                                      State:\tR (running)\n\
+                                                      VmRSS:\t  100 kB\n").unwrap();
+        assert_eq!(status.name, "foo");
+        assert_eq!(status.vm_rss, Some(100 * 1024));
+        assert_eq!(status.rss_anon, None);
+        assert_eq!(status.cpus_allowed_list, None);
+
+        assert!(parse_status("/proc/7/status", "State:\tR (running)\n").is_err());
+    }
+}
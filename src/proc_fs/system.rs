@@ -0,0 +1,295 @@
+/*
+ * Host wide CPU statistics from /proc/stat
+ */
+use std::thread;
+
+use proc_fs::{ProcFs, ProcError, read_proc_file, parse_field};
+
+// All times are in clock ticks (jiffies) since boot.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct CpuTimes {
+    pub cpu: Option<usize>, // None for the aggregate of all cpus
+    pub user: usize,
+    pub nice: usize,
+    pub system: usize,
+    pub idle: usize,
+    pub iowait: usize,
+    pub irq: usize,
+    pub softirq: usize,
+    pub steal: usize,
+    pub guest: usize, // already included in user
+    pub guest_nice: usize, // already included in nice
+}
+
+impl CpuTimes {
+    /// Ticks spent in every state. Guest time is counted in user and nice
+    /// too, so it is left out.
+    pub fn total(&self) -> usize {
+        self.user + self.nice + self.system + self.idle + self.iowait +
+            self.irq + self.softirq + self.steal
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SystemStat {
+    pub total: CpuTimes,
+    pub cpus: Vec<CpuTimes>,
+    pub context_switches: usize,
+    pub interrupts: usize, // all interrupts serviced since boot
+    pub boot_time: usize, // seconds since the epoch
+    pub processes: usize, // forks since boot
+    pub procs_running: usize,
+    pub procs_blocked: usize, // waiting for I/O
+}
+
+// Share of the elapsed ticks spent in each state between two samples.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct CpuPercent {
+    pub cpu: Option<usize>,
+    pub user: f64,
+    pub nice: f64,
+    pub system: f64,
+    pub idle: f64,
+    pub iowait: f64,
+    pub irq: f64,
+    pub softirq: f64,
+    pub steal: f64,
+    pub guest: f64,
+    pub guest_nice: f64,
+    pub busy: f64, // everything but idle and iowait
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SystemCpu {
+    pub total: CpuPercent,
+    pub cpus: Vec<CpuPercent>,
+    pub context_switches: usize, // during the window
+    pub interrupts: usize, // during the window
+    pub forks: usize, // during the window
+    pub procs_running: usize,
+    pub procs_blocked: usize,
+    pub window_ticks: usize, // jiffies elapsed on a single cpu between samples
+}
+
+fn parse_cpu_line(path: &str, line: usize, name: &str, fields: &[&str])
+    -> Result<CpuTimes, ProcError> {
+    let cpu = if name == "cpu" {
+        None
+    } else {
+        Some(try!(parse_field(path, line, name, &name[3..])))
+    };
+
+    // Older kernels have fewer columns: steal came in 2.6.11, guest in
+    // 2.6.24 and guest_nice in 2.6.33.
+    let mut values = [0; 10];
+    for (i, value) in fields.iter().take(10).enumerate() {
+        values[i] = try!(parse_field(path, line, name, value));
+    }
+    if fields.len() < 4 {
+        return Err(ProcError::parse(path, line, name))
+    }
+
+    Ok(CpuTimes {
+        cpu: cpu,
+        user: values[0],
+        nice: values[1],
+        system: values[2],
+        idle: values[3],
+        iowait: values[4],
+        irq: values[5],
+        softirq: values[6],
+        steal: values[7],
+        guest: values[8],
+        guest_nice: values[9],
+    })
+}
+
+pub fn parse_system_stat(path: &str, stat: &str) -> Result<SystemStat, ProcError> {
+    let mut total = None;
+    let mut cpus = Vec::new();
+    let mut counters = [0; 6];
+    let names = ["ctxt", "intr", "btime", "processes", "procs_running", "procs_blocked"];
+
+    for (i, line) in stat.lines().enumerate() {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.is_empty() {
+            continue
+        }
+
+        match fields[0] {
+            "cpu" => total = Some(try!(parse_cpu_line(path, i + 1, fields[0], &fields[1..]))),
+            name if name.starts_with("cpu") =>
+                cpus.push(try!(parse_cpu_line(path, i + 1, name, &fields[1..]))),
+            // intr is followed by the count of every interrupt line; only
+            // the total is kept.
+            name => if let Some(n) = names.iter().position(|k| *k == name) {
+                match fields.get(1) {
+                    Some(v) => counters[n] = try!(parse_field(path, i + 1, name, v)),
+                    None => return Err(ProcError::parse(path, i + 1, name)),
+                }
+            },
+        }
+    }
+
+    let total = match total {
+        Some(total) => total,
+        None => return Err(ProcError::UnexpectedFormat(format!("{}: no cpu line", path))),
+    };
+
+    Ok(SystemStat {
+        total: total,
+        cpus: cpus,
+        context_switches: counters[0],
+        interrupts: counters[1],
+        boot_time: counters[2],
+        processes: counters[3],
+        procs_running: counters[4],
+        procs_blocked: counters[5],
+    })
+}
+
+fn cpu_percent(prev: &CpuTimes, cur: &CpuTimes) -> CpuPercent {
+    let elapsed = cur.total().saturating_sub(prev.total());
+    let percent = |prev: usize, cur: usize| {
+        if elapsed == 0 { 0.0 } else { 100.0 * cur.saturating_sub(prev) as f64 / elapsed as f64 }
+    };
+    let idle = percent(prev.idle, cur.idle);
+    let iowait = percent(prev.iowait, cur.iowait);
+
+    CpuPercent {
+        cpu: cur.cpu,
+        user: percent(prev.user, cur.user),
+        nice: percent(prev.nice, cur.nice),
+        system: percent(prev.system, cur.system),
+        idle: idle,
+        iowait: iowait,
+        irq: percent(prev.irq, cur.irq),
+        softirq: percent(prev.softirq, cur.softirq),
+        steal: percent(prev.steal, cur.steal),
+        guest: percent(prev.guest, cur.guest),
+        guest_nice: percent(prev.guest_nice, cur.guest_nice),
+        busy: if elapsed == 0 { 0.0 } else { 100.0 - idle - iowait },
+    }
+}
+
+/// Utilisation between two samples. CPUs that went offline or came online
+/// in between are left out of the per-cpu list.
+pub fn system_cpu_usage(prev: &SystemStat, cur: &SystemStat) -> SystemCpu {
+    let cpus = cur.cpus.iter()
+        .filter_map(|c| prev.cpus.iter().find(|p| p.cpu == c.cpu).map(|p| cpu_percent(p, c)))
+        .collect();
+    let online = if cur.cpus.is_empty() { 1 } else { cur.cpus.len() };
+
+    SystemCpu {
+        total: cpu_percent(&prev.total, &cur.total),
+        cpus: cpus,
+        context_switches: cur.context_switches.saturating_sub(prev.context_switches),
+        interrupts: cur.interrupts.saturating_sub(prev.interrupts),
+        forks: cur.processes.saturating_sub(prev.processes),
+        procs_running: cur.procs_running,
+        procs_blocked: cur.procs_blocked,
+        window_ticks: cur.total.total().saturating_sub(prev.total.total()) / online,
+    }
+}
+
+impl ProcFs {
+    pub fn system_stat(&self) -> Result<SystemStat, ProcError> {
+        let path = self.path("stat");
+        let stat = try!(read_proc_file(&path));
+
+        parse_system_stat(&path, &stat)
+    }
+
+    /// Takes two samples `window_ms` apart and reports the usage in between.
+    pub fn system_cpu(&self, window_ms: u32) -> Result<SystemCpu, ProcError> {
+        let first = try!(self.system_stat());
+        thread::sleep_ms(window_ms);
+        let second = try!(self.system_stat());
+
+        Ok(system_cpu_usage(&first, &second))
+    }
+}
+
+/// Keeps the previous sample around so that a periodic caller gets the usage
+/// since its last call.
+pub struct SystemCpuSampler {
+    proc_fs: ProcFs,
+    last: Option<SystemStat>,
+}
+
+impl SystemCpuSampler {
+    pub fn new(proc_fs: ProcFs) -> SystemCpuSampler {
+        SystemCpuSampler {
+            proc_fs: proc_fs,
+            last: None,
+        }
+    }
+
+    /// Returns `None` on the first call, since there is nothing to compare to.
+    pub fn sample(&mut self) -> Result<Option<SystemCpu>, ProcError> {
+        let cur = try!(self.proc_fs.system_stat());
+        let usage = self.last.as_ref().map(|prev| system_cpu_usage(prev, &cur));
+        self.last = Some(cur);
+        Ok(usage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proc_fs::system::*;
+    use proc_fs::{ProcFs, fixtures};
+
+    #[test]
+    fn test_system_stat() {
+        let stat = ProcFs::default().system_stat().unwrap();
+        assert!(stat.total.total() > 0);
+        assert!(stat.cpus.len() > 0);
+        assert!(stat.boot_time > 0);
+    }
+
+    #[test]
+    fn test_system_stat_fixture() {
+        let stat = fixtures().system_stat().unwrap();
+        assert_eq!(stat.total.cpu, None);
+        assert_eq!(stat.total.user, 10132153);
+        assert_eq!(stat.total.guest, 175628);
+        assert_eq!(stat.total.total(), 60377929);
+        assert_eq!(stat.cpus.len(), 4);
+        assert_eq!(stat.cpus[2].cpu, Some(2));
+        assert_eq!(stat.cpus[2].idle, 10122618);
+        assert_eq!(stat.context_switches, 2718957);
+        assert_eq!(stat.interrupts, 1462898);
+        assert_eq!(stat.boot_time, 1439401563);
+        assert_eq!(stat.processes, 31337);
+        assert_eq!(stat.procs_running, 2);
+        assert_eq!(stat.procs_blocked, 0);
+    }
+
+    #[test]
+    fn test_system_cpu_usage() {
+        let prev = parse_system_stat("stat", "cpu 100 0 100 700 100 0 0 0 0 0\n\
+                                              cpu0 100 0 100 700 100 0 0 0 0 0\n\
+                                              ctxt 1000\nprocesses 10\n").unwrap();
+        let cur = parse_system_stat("stat", "cpu 150 0 150 900 100 0 0 0 20 0\n\
+                                             cpu0 150 0 150 900 100 0 0 0 20 0\n\
+                                             ctxt 1600\nprocesses 12\n").unwrap();
+
+        let usage = system_cpu_usage(&prev, &cur);
+        assert_eq!(usage.total.user, 16.666666666666668);
+        assert_eq!(usage.total.idle, 66.66666666666667);
+        assert_eq!(usage.total.iowait, 0.0);
+        assert_eq!(usage.total.guest, 6.666666666666667);
+        assert_eq!(usage.cpus.len(), 1);
+        assert_eq!(usage.cpus[0].cpu, Some(0));
+        assert_eq!(usage.context_switches, 600);
+        assert_eq!(usage.forks, 2);
+        assert_eq!(usage.window_ticks, 300);
+    }
+
+    #[test]
+    fn test_system_cpu_sampler() {
+        let mut sampler = SystemCpuSampler::new(ProcFs::default());
+        assert_eq!(sampler.sample().unwrap(), None);
+        assert!(sampler.sample().unwrap().is_some());
+    }
+}
@@ -0,0 +1,135 @@
+/*
+ * Per-thread statistics from /proc/<pid>/task/<tid>
+ */
+use std::fs;
+use std::string::String;
+use std::str::FromStr;
+
+use proc_fs::{ProcFs, Pid, ProcError};
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ThreadStat {
+    pub tid: u32,
+    pub comm: String,
+    pub state: char,
+    pub utime: usize, // clock ticks scheduled in usermode
+    pub stime: usize, // clock ticks scheduled in kernel mode
+    pub processor: usize, // CPU number last executed on
+    pub voluntary_ctxt_switches: Option<usize>,
+    pub nonvoluntary_ctxt_switches: Option<usize>,
+    pub kernel_stack: Option<String>, // None if we may not read the stack or it is not there
+}
+
+impl ProcFs {
+    /// A handle on /proc/<pid>/task, whose entries have the same layout as
+    /// the process directories, so every per-process collector works on
+    /// threads too.
+    pub fn tasks(&self, pid: Pid) -> ProcFs {
+        ProcFs::new(self.root().join(format!("{}/task", pid)))
+    }
+
+    pub fn process_tids(&self, pid: Pid) -> Result<Vec<u32>, ProcError> {
+        let dir = self.path(&format!("{}/task", pid));
+        let entries = try!(fs::read_dir(&dir).map_err(|e| ProcError::from_io(&dir, e)));
+
+        let mut tids = Vec::new();
+        for entry in entries {
+            let entry = try!(entry.map_err(|e| ProcError::from_io(&dir, e)));
+            if let Ok(tid) = u32::from_str(&entry.file_name().to_string_lossy()) {
+                tids.push(tid);
+            }
+        }
+
+        tids.sort();
+        Ok(tids)
+    }
+
+    /// Statistics for every thread of the process. Threads that exit while
+    /// we are reading them are skipped.
+    pub fn process_threads(&self, pid: Pid) -> Result<Vec<ThreadStat>, ProcError> {
+        let tasks = self.tasks(pid);
+        let mut threads = Vec::new();
+
+        for tid in try!(self.process_tids(pid)) {
+            match tasks.thread_stat(tid) {
+                Ok(thread) => threads.push(thread),
+                Err(ProcError::NotFound(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(threads)
+    }
+
+    // Reads a single task, with self being the task directory.
+    fn thread_stat(&self, tid: u32) -> Result<ThreadStat, ProcError> {
+        let stat = try!(self.process_stat(Pid::Id(tid)));
+        let status = try!(self.process_status(Pid::Id(tid)));
+        let stack = match self.process_stack(Pid::Id(tid)) {
+            Ok(stack) => Some(stack.kernel_trace),
+            // Kernels without CONFIG_STACKTRACE have no stack file.
+            Err(ProcError::PermissionDenied(_)) | Err(ProcError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(ThreadStat {
+            tid: tid,
+            comm: stat.command,
+            state: stat.state,
+            utime: stat.utime,
+            stime: stat.stime,
+            processor: stat.processor,
+            voluntary_ctxt_switches: status.voluntary_ctxt_switches,
+            nonvoluntary_ctxt_switches: status.nonvoluntary_ctxt_switches,
+            kernel_stack: stack,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::sync::mpsc::channel;
+    use proc_fs::{ProcFs, Pid, fixtures};
+
+    #[test]
+    fn test_proc_threads() {
+        let (tx, rx) = channel::<()>();
+        let handle = thread::spawn(move || {
+            let _ = rx.recv();
+        });
+
+        let proc_fs = ProcFs::default();
+        let threads = proc_fs.process_threads(Pid::Current).unwrap();
+        assert!(threads.len() >= 2);
+
+        tx.send(()).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_proc_threads_fixture() {
+        let proc_fs = fixtures();
+        assert_eq!(proc_fs.process_tids(Pid::Id(1)).unwrap(), vec![1, 12]);
+
+        let threads = proc_fs.process_threads(Pid::Id(1)).unwrap();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].comm, "init");
+        assert_eq!(threads[1].tid, 12);
+        assert_eq!(threads[1].comm, "worker 1");
+        assert_eq!(threads[1].state, 'D');
+        assert_eq!(threads[1].utime, 5021);
+        assert_eq!(threads[1].processor, 2);
+        assert_eq!(threads[1].voluntary_ctxt_switches, Some(830));
+        assert!(threads[1].kernel_stack.as_ref().unwrap().contains("io_schedule"));
+    }
+
+    #[test]
+    fn test_proc_threads_without_stack() {
+        // fixtures/proc/412/task/412 has no stack file
+        let threads = fixtures().process_threads(Pid::Id(412)).unwrap();
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].tid, 412);
+        assert_eq!(threads[0].kernel_stack, None);
+    }
+}
@@ -0,0 +1,199 @@
+/*
+ * Process trees built from the ppid links in /proc/<pid>/stat, and totals
+ * across a subtree so a supervisor and its children can be measured as one.
+ */
+use std::collections::BTreeMap;
+use std::string::String;
+
+use proc_fs::{ProcFs, Pid, ProcError};
+use proc_fs::processes::ProcessSummary;
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProcessTree {
+    pub process: ProcessSummary,
+    pub children: Vec<ProcessTree>, // in pid order
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct SubtreeTotals {
+    pub pid: u32, // root of the subtree
+    pub processes: usize,
+    pub rss: usize, // bytes
+    pub cpu_ticks: usize,
+    pub read_bytes: usize, // from storage, per ProcIo
+    pub write_bytes: usize,
+    pub fds: usize,
+    pub unreadable: usize, // processes whose io or fds we may not read
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProcessTreeReport {
+    pub tree: ProcessTree,
+    pub totals: SubtreeTotals,
+}
+
+impl ProcessTree {
+    /// The root and all of its descendants, depth first.
+    pub fn pids(&self) -> Vec<u32> {
+        let mut pids = vec![self.process.pid];
+        for child in self.children.iter() {
+            pids.extend(child.pids());
+        }
+        pids
+    }
+}
+
+/// Builds the tree below `root` from a process listing. None if `root` is
+/// not in the listing.
+pub fn build_tree(processes: Vec<ProcessSummary>, root: u32) -> Option<ProcessTree> {
+    let mut by_pid = BTreeMap::new();
+    let mut children: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for process in processes.into_iter() {
+        // pid 0 is the parent of init and kthreadd, not a process.
+        if process.ppid != process.pid {
+            children.entry(process.ppid).or_insert(Vec::new()).push(process.pid);
+        }
+        by_pid.insert(process.pid, process);
+    }
+
+    build_subtree(&mut by_pid, &children, root)
+}
+
+fn build_subtree(by_pid: &mut BTreeMap<u32, ProcessSummary>, children: &BTreeMap<u32, Vec<u32>>,
+                 pid: u32) -> Option<ProcessTree> {
+    // Removing each process as it is placed guards against ppid cycles,
+    // which pid reuse between reads can produce.
+    let process = match by_pid.remove(&pid) {
+        Some(process) => process,
+        None => return None,
+    };
+
+    let mut subtrees = Vec::new();
+    if let Some(child_pids) = children.get(&pid) {
+        for child in child_pids.iter() {
+            if let Some(subtree) = build_subtree(by_pid, children, *child) {
+                subtrees.push(subtree);
+            }
+        }
+    }
+
+    Some(ProcessTree {
+        process: process,
+        children: subtrees,
+    })
+}
+
+impl ProcFs {
+    pub fn process_tree(&self, pid: Pid) -> Result<ProcessTree, ProcError> {
+        // Resolves self and thread-self to a number.
+        let root = try!(self.process_stat(pid)).pid as u32;
+        let processes = try!(self.processes());
+
+        match build_tree(processes, root) {
+            Some(tree) => Ok(tree),
+            None => Err(ProcError::NotFound(self.path(&format!("{}", root)))),
+        }
+    }
+
+    /// Totals across the tree. Processes that exit meanwhile are left out.
+    pub fn subtree_totals(&self, tree: &ProcessTree) -> Result<SubtreeTotals, ProcError> {
+        let mut totals = SubtreeTotals::default();
+        totals.pid = tree.process.pid;
+        try!(self.add_subtree(tree, &mut totals));
+        Ok(totals)
+    }
+
+    fn add_subtree(&self, tree: &ProcessTree, totals: &mut SubtreeTotals) -> Result<(), ProcError> {
+        let pid = Pid::Id(tree.process.pid);
+        let io = self.process_io(pid);
+        let fds = self.process_fds(pid);
+        let mut unreadable = false;
+
+        match io {
+            Ok(io) => {
+                totals.read_bytes += io.read_bytes;
+                totals.write_bytes += io.write_bytes;
+            },
+            Err(ProcError::PermissionDenied(_)) => unreadable = true,
+            Err(ProcError::NotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        match fds {
+            Ok(fds) => totals.fds += fds.len(),
+            Err(ProcError::PermissionDenied(_)) => unreadable = true,
+            Err(ProcError::NotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        totals.processes += 1;
+        totals.rss += tree.process.rss;
+        totals.cpu_ticks += tree.process.cpu_ticks;
+        if unreadable {
+            totals.unreadable += 1;
+        }
+
+        for child in tree.children.iter() {
+            try!(self.add_subtree(child, totals));
+        }
+        Ok(())
+    }
+
+    pub fn process_tree_report(&self, pid: Pid) -> Result<ProcessTreeReport, ProcError> {
+        let tree = try!(self.process_tree(pid));
+        let totals = try!(self.subtree_totals(&tree));
+
+        Ok(ProcessTreeReport {
+            tree: tree,
+            totals: totals,
+        })
+    }
+
+    pub fn process_subtree_totals(&self, pid: Pid) -> Result<SubtreeTotals, ProcError> {
+        let tree = try!(self.process_tree(pid));
+        self.subtree_totals(&tree)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+    use proc_fs::tree::*;
+    use proc_fs::{ProcFs, Pid, fixtures, page_size};
+
+    #[test]
+    fn test_process_tree() {
+        let mut child = Command::new("sh").arg("-c").arg("sleep 1").spawn().unwrap();
+
+        let tree = ProcFs::default().process_tree(Pid::Current).unwrap();
+        assert!(tree.pids().contains(&child.id()));
+
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_process_tree_fixture() {
+        let tree = fixtures().process_tree(Pid::Id(1)).unwrap();
+        assert_eq!(tree.pids(), vec![1, 412, 413, 414]);
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!(tree.children[0].process.comm, "supervisord");
+        assert_eq!(tree.children[0].children.len(), 2);
+
+        let report = fixtures().process_tree_report(Pid::Id(412)).unwrap();
+        assert_eq!(report.tree.pids(), vec![412, 413, 414]);
+        let totals = report.totals;
+        assert_eq!(totals.pid, 412);
+        assert_eq!(totals.processes, 3);
+        assert_eq!(totals.rss, (4096 + 25600 + 12800) * page_size());
+        assert_eq!(totals.cpu_ticks, 1800 + 102000 + 53000);
+        assert_eq!(totals.read_bytes, 4096 + 1048576);
+        assert_eq!(totals.write_bytes, 8192 + 524288 + 65536);
+        assert_eq!(totals.fds, 3 + 4 + 2);
+        assert_eq!(totals.unreadable, 0);
+    }
+
+    #[test]
+    fn test_build_tree_missing_root() {
+        let processes = fixtures().processes().unwrap();
+        assert!(build_tree(processes, 999).is_none());
+    }
+}
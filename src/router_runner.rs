@@ -1,12 +1,19 @@
-use proc_fs::stats::*;
-use proc_fs::kernel::*;
-use proc_fs::net::*;
-use proc_fs::ToPid;
-use iron::{Iron, IronResult, Request, Response};
+use proc_fs::{ProcFs, Pid, ProcError};
+use proc_fs::memory::{group_by_file, group_by_library};
+use proc_fs::processes::{ProcessFilter, SortKey};
+use proc_fs::netdev::InterfaceFilter;
+use proc_fs::sockets::{SocketFilter, SocketState, Protocol};
+use proc_fs::diskstats::DiskFilter;
+use proc_fs::filesystems::FilesystemFilter;
+use iron::{status, Iron, IronResult, Request, Response, Handler};
 use router::Router;
 
+use std::any::Any;
 use std::sync::{Arc, Mutex, Condvar};
+use std::str::{self, FromStr};
 
+use regex::Regex;
+use serde::Serialize;
 use serde_json;
 
 use marid::{MaridError, Runner, Signal, Receiver};
@@ -17,12 +24,36 @@ pub struct RouterRunner {
 }
 
 impl RouterRunner {
-    pub fn new() -> RouterRunner {
+    /// `redact` lists the environment variable name fragments whose values
+    /// /proc/:pid/info hides.
+    pub fn new(proc_fs: ProcFs, redact: Vec<String>) -> RouterRunner {
         let mut router = Router::new();
-        router.get("/proc/:pid/statm", proc_statm_handler);
-        router.get("/proc/:pid/io", proc_io_handler);
-        router.get("/proc/:pid/stack", proc_stack_handler);
-        router.get("/net/tcpstats", proc_tcp_handler);
+        router.get("/proc/:pid/stat", with_proc_fs(&proc_fs, proc_stat_handler));
+        router.get("/proc/:pid/status", with_proc_fs(&proc_fs, proc_status_handler));
+        router.get("/proc/:pid/statm", with_proc_fs(&proc_fs, proc_statm_handler));
+        router.get("/proc/:pid/smaps", with_proc_fs(&proc_fs, proc_smaps_handler));
+        router.get("/proc/:pid/fds", with_proc_fs(&proc_fs, proc_fds_handler));
+        router.get("/proc/:pid/io", with_proc_fs(&proc_fs, proc_io_handler));
+        router.get("/proc/:pid/cpu", with_proc_fs(&proc_fs, proc_cpu_handler));
+        router.get("/proc/:pid/threads", with_proc_fs(&proc_fs, proc_threads_handler));
+        router.get("/proc/:pid/limits", with_proc_fs(&proc_fs, proc_limits_handler));
+        router.get("/proc/:pid/stack", with_proc_fs(&proc_fs, proc_stack_handler));
+        router.get("/proc/:pid/tree", with_proc_fs(&proc_fs, proc_tree_handler));
+        router.get("/proc/:pid/sockets", with_proc_fs(&proc_fs, proc_sockets_handler));
+        router.get("/proc/:pid/filesystems", with_proc_fs(&proc_fs, proc_filesystems_handler));
+        router.get("/proc/:pid/info", InfoHandler { proc_fs: proc_fs.clone(), redact: redact });
+        router.get("/system/cpu", with_proc_fs(&proc_fs, system_cpu_handler));
+        router.get("/system/memory", with_proc_fs(&proc_fs, system_memory_handler));
+        router.get("/system/load", with_proc_fs(&proc_fs, system_load_handler));
+        router.get("/system/pressure", with_proc_fs(&proc_fs, system_pressure_handler));
+        router.get("/system/disks", with_proc_fs(&proc_fs, system_disks_handler));
+        router.get("/system/filesystems", with_proc_fs(&proc_fs, system_filesystems_handler));
+        router.get("/processes", with_proc_fs(&proc_fs, processes_handler));
+        router.get("/net/interfaces", with_proc_fs(&proc_fs, net_interfaces_handler));
+        router.get("/net/connections", with_proc_fs(&proc_fs, net_connections_handler));
+        router.get("/net/sockstat", with_proc_fs(&proc_fs, net_sockstat_handler));
+        router.get("/net/snmp/:section", with_proc_fs(&proc_fs, net_snmp_handler));
+        router.get("/net/tcpstats", with_proc_fs(&proc_fs, proc_tcp_handler));
 
         RouterRunner {
             router: Some(router),
@@ -61,29 +92,367 @@ impl Runner for RouterRunner {
     }
 }
 
-fn proc_statm_handler(req: &mut Request) -> IronResult<Response> {
-    let ref pid = req.extensions.get::<Router>().unwrap().find("pid").unwrap_or("/");
-    let stats = process_statm((*pid).to_pid()).unwrap();
-    let serialized = serde_json::to_string(&stats).unwrap();
-    Ok(Response::with(serialized))
+// A handler bound to the proc filesystem it should read from.
+struct ProcHandler<F> {
+    proc_fs: ProcFs,
+    handler: F,
 }
 
-fn proc_io_handler(req: &mut Request) -> IronResult<Response> {
-    let ref pid = req.extensions.get::<Router>().unwrap().find("pid").unwrap_or("/");
-    let stats = process_io((*pid).to_pid()).unwrap();
-    let serialized = serde_json::to_string(&stats).unwrap();
-    Ok(Response::with(serialized))
+impl<F> Handler for ProcHandler<F>
+    where F: Fn(&ProcFs, &mut Request) -> IronResult<Response> + Send + Sync + Any {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        (self.handler)(&self.proc_fs, req)
+    }
+}
+
+fn with_proc_fs<F>(proc_fs: &ProcFs, handler: F) -> ProcHandler<F> {
+    ProcHandler {
+        proc_fs: proc_fs.clone(),
+        handler: handler,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+fn error_response(code: status::Status, error: String) -> Response {
+    let body = ErrorResponse {
+        error: error,
+    };
+    let serialized = serde_json::to_string(&body).unwrap();
+    Response::with((code, serialized))
+}
+
+fn json_response<T: Serialize>(result: Result<T, ProcError>) -> IronResult<Response> {
+    match result {
+        Ok(data) => {
+            let serialized = serde_json::to_string(&data).unwrap();
+            Ok(Response::with((status::Ok, serialized)))
+        },
+        Err(err) => {
+            let code = match err {
+                ProcError::NotFound(_) => status::NotFound,
+                ProcError::PermissionDenied(_) => status::Forbidden,
+                _ => status::InternalServerError,
+            };
+            Ok(error_response(code, format!("{}", err)))
+        },
+    }
+}
+
+fn pid_param(req: &Request) -> Result<Pid, Response> {
+    let pid = req.extensions.get::<Router>().unwrap().find("pid").unwrap_or("");
+    Pid::from_str(pid).map_err(|e| error_response(status::BadRequest, format!("{}", e)))
+}
+
+// Returns a 400 from the handler if the :pid segment is not a valid pid.
+macro_rules! try_pid {
+    ($req:expr) => (match pid_param($req) {
+        Ok(pid) => pid,
+        Err(res) => return Ok(res),
+    })
+}
+
+fn proc_stat_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_stat(pid))
+}
+
+fn proc_status_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_status(pid))
+}
+
+fn proc_statm_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_statm(pid))
+}
+
+fn proc_io_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_io(pid))
+}
+
+fn proc_fds_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_fd_inventory(pid))
+}
+
+fn proc_limits_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_limits_report(pid))
+}
+
+fn proc_stack_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_stack(pid))
+}
+
+fn proc_threads_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_threads(pid))
+}
+
+fn proc_tree_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_tree_report(pid))
+}
+
+// Default and maximum ?window= for the endpoints that report rates between
+// two samples, such as /system/cpu or /net/interfaces, in milliseconds.
+const DEFAULT_WINDOW: u32 = 1000;
+const MAX_WINDOW: u32 = 10000;
+
+fn query_param(req: &Request, key: &str) -> Option<String> {
+    req.url.query.as_ref().and_then(|query| {
+        query.split('&')
+            .filter_map(|pair| {
+                let mut kv = pair.splitn(2, '=');
+                match (kv.next(), kv.next()) {
+                    (Some(k), Some(v)) if k == key => Some(percent_decode(v)),
+                    _ => None,
+                }
+            })
+            .next()
+    })
+}
+
+// Decodes '+' and %XX escapes in a query value, so that e.g. a regex can
+// be passed as ?command=%5Eworker.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok());
+                match hex {
+                    Some(b) => {
+                        decoded.push(b);
+                        i += 2;
+                    },
+                    None => decoded.push(b'%'),
+                }
+            },
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn window_param(req: &Request) -> u32 {
+    query_param(req, "window")
+        .and_then(|w| u32::from_str(w.as_str()).ok())
+        .map(|w| if w > MAX_WINDOW { MAX_WINDOW } else { w })
+        .unwrap_or(DEFAULT_WINDOW)
+}
+
+fn proc_cpu_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let window = window_param(req);
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_cpu(pid, window))
+}
+
+fn system_cpu_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let window = window_param(req);
+    json_response(proc_fs.system_cpu(window))
+}
+
+// /proc/:pid/smaps groups mappings by backing file, or with
+// ?aggregate=library only reports totals per library.
+fn proc_smaps_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let by_library = query_param(req, "aggregate").map_or(false, |a| a == "library");
+    let pid = try_pid!(req);
+    let mappings = proc_fs.process_smaps(pid);
+    if by_library {
+        json_response(mappings.map(group_by_library))
+    } else {
+        json_response(mappings.map(group_by_file))
+    }
+}
+
+// /proc/:pid/info only includes the environment with ?environ=true, and
+// then with the configured variables redacted.
+struct InfoHandler {
+    proc_fs: ProcFs,
+    redact: Vec<String>,
+}
+
+impl Handler for InfoHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let environ = query_param(req, "environ").map_or(false, |e| e == "true");
+        let pid = try_pid!(req);
+        let redact = if environ { Some(&self.redact[..]) } else { None };
+        json_response(self.proc_fs.process_identity(pid, redact))
+    }
+}
+
+fn filter_param(req: &Request) -> Result<ProcessFilter, String> {
+    let mut filter = ProcessFilter::default();
+    filter.user = query_param(req, "user");
+    if let Some(command) = query_param(req, "command") {
+        filter.command = Some(try!(Regex::new(&command).map_err(|e| format!("{}", e))));
+    }
+    if let Some(state) = query_param(req, "state") {
+        let mut chars = state.chars();
+        filter.state = match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => return Err(format!("state must be a single letter, got '{}'", state)),
+        };
+    }
+    if let Some(sort) = query_param(req, "sort") {
+        filter.sort = try!(SortKey::from_str(&sort));
+    }
+    if let Some(limit) = query_param(req, "limit") {
+        filter.limit = Some(try!(usize::from_str(&limit)
+                                 .map_err(|_| format!("invalid limit '{}'", limit))));
+    }
+    Ok(filter)
+}
+
+// /processes lists every process, optionally filtered with ?user=,
+// ?command= (a regex on the command name) and ?state=, ordered with
+// ?sort=pid|comm|rss|cpu and truncated with ?limit=.
+fn processes_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let filter = match filter_param(req) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(error_response(status::BadRequest, e)),
+    };
+    json_response(proc_fs.processes().map(|processes| filter.apply(processes)))
+}
+
+fn system_memory_handler(proc_fs: &ProcFs, _req: &mut Request) -> IronResult<Response> {
+    json_response(proc_fs.system_memory())
+}
+
+// /system/disks reports device activity over ?window= ms, leaving out
+// partitions with ?partitions=false and loop devices with ?loop=false.
+fn system_disks_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let filter = DiskFilter {
+        skip_partitions: query_param(req, "partitions").map_or(false, |p| p == "false"),
+        skip_loop: query_param(req, "loop").map_or(false, |l| l == "false"),
+    };
+    let window = window_param(req);
+    json_response(proc_fs.disks(filter, window))
+}
+
+fn filesystem_filter_param(req: &Request) -> Result<FilesystemFilter, String> {
+    let mut filter = FilesystemFilter::default();
+    if let Some(types) = query_param(req, "type") {
+        filter.fs_types = types.split(',').filter(|t| !t.is_empty()).map(String::from).collect();
+    }
+    if let Some(mount) = query_param(req, "mount") {
+        filter.mount_point = Some(try!(Regex::new(&mount).map_err(|e| format!("{}", e))));
+    }
+    Ok(filter)
+}
+
+macro_rules! try_filesystem_filter {
+    ($req:expr) => (match filesystem_filter_param($req) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(error_response(status::BadRequest, e)),
+    })
+}
+
+// /system/filesystems lists the filesystems mounted in our mount namespace,
+// /proc/:pid/filesystems those in the process's. Both take ?type=ext4,xfs
+// and ?mount= (a regex on the mount point).
+fn system_filesystems_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let filter = try_filesystem_filter!(req);
+    json_response(proc_fs.filesystems(Pid::Current, &filter))
+}
+
+fn proc_filesystems_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let filter = try_filesystem_filter!(req);
+    let pid = try_pid!(req);
+    json_response(proc_fs.filesystems(pid, &filter))
+}
+
+fn system_load_handler(proc_fs: &ProcFs, _req: &mut Request) -> IronResult<Response> {
+    json_response(proc_fs.loadavg())
+}
+
+// Resources the kernel has no pressure information for are null.
+fn system_pressure_handler(proc_fs: &ProcFs, _req: &mut Request) -> IronResult<Response> {
+    json_response(proc_fs.system_pressure())
+}
+
+fn interface_filter_param(req: &Request) -> Result<InterfaceFilter, String> {
+    let regex = |key: &str| match query_param(req, key) {
+        Some(r) => Regex::new(&r).map(Some).map_err(|e| format!("{}: {}", key, e)),
+        None => Ok(None),
+    };
+    Ok(InterfaceFilter {
+        include: try!(regex("include")),
+        exclude: try!(regex("exclude")),
+    })
+}
+
+// /net/interfaces reports counters and rates over ?window= ms for the
+// interfaces matching the ?include= and not the ?exclude= regex.
+fn net_interfaces_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let filter = match interface_filter_param(req) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(error_response(status::BadRequest, e)),
+    };
+    let window = window_param(req);
+    json_response(proc_fs.net_interfaces(&filter, window))
+}
+
+// /net/snmp/:section serves any section of /proc/net/snmp, netstat or snmp6
+// by name, e.g. /net/snmp/TcpExt or /net/snmp/Udp6.
+fn net_snmp_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let section = String::from(req.extensions.get::<Router>().unwrap().find("section").unwrap_or(""));
+    json_response(proc_fs.net_snmp_section(&section))
+}
+
+fn socket_filter_param(req: &Request) -> Result<SocketFilter, String> {
+    let mut filter = SocketFilter::default();
+    if let Some(protocol) = query_param(req, "protocol") {
+        filter.protocol = Some(try!(Protocol::from_str(&protocol)));
+    }
+    if let Some(state) = query_param(req, "state") {
+        filter.state = Some(try!(SocketState::from_str(&state)));
+    }
+    if let Some(port) = query_param(req, "port") {
+        filter.port = Some(try!(u16::from_str(&port).map_err(|_| format!("invalid port '{}'", port))));
+    }
+    Ok(filter)
+}
+
+macro_rules! try_socket_filter {
+    ($req:expr) => (match socket_filter_param($req) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(error_response(status::BadRequest, e)),
+    })
+}
+
+// /proc/:pid/sockets and /net/connections take ?protocol=tcp|tcp6|udp|udp6|unix,
+// ?state= as ss names it (e.g. listen, time-wait) and ?port=, which matches
+// either end.
+fn proc_sockets_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let filter = try_socket_filter!(req);
+    let pid = try_pid!(req);
+    json_response(proc_fs.process_sockets(pid).map(|sockets| {
+        sockets.into_iter().filter(|s| filter.matches(&s.socket)).collect::<Vec<_>>()
+    }))
+}
+
+fn net_connections_handler(proc_fs: &ProcFs, req: &mut Request) -> IronResult<Response> {
+    let filter = try_socket_filter!(req);
+    json_response(proc_fs.connections(&filter))
 }
 
-fn proc_stack_handler(req: &mut Request) -> IronResult<Response> {
-    let ref pid = req.extensions.get::<Router>().unwrap().find("pid").unwrap_or("/");
-    let stack_trace = process_stack((*pid).to_pid()).unwrap();
-    let serialized = serde_json::to_string(&stack_trace).unwrap();
-    Ok(Response::with(serialized))
+fn net_sockstat_handler(proc_fs: &ProcFs, _req: &mut Request) -> IronResult<Response> {
+    json_response(proc_fs.socket_summary())
 }
 
-fn proc_tcp_handler(_req: &mut Request) -> IronResult<Response> {
-    let tcp = process_tcp().unwrap();
-    let serialized = serde_json::to_string(&tcp).unwrap();
-    Ok(Response::with(serialized))
+fn proc_tcp_handler(proc_fs: &ProcFs, _req: &mut Request) -> IronResult<Response> {
+    json_response(proc_fs.process_tcp())
 }